@@ -13,6 +13,7 @@ import os
 import shutil
 import subprocess
 import sys
+import tempfile
 from subprocess import SubprocessError
 from typing import List, Dict
 
@@ -21,15 +22,19 @@ import toml
 # these are only used when creating the sdist, not when building it
 create_only_options = [
     "sdist-include",
+    "sdist-exclude",
 ]
 
 available_options = [
     "bindings",
     "cargo-extra-args",
+    "manifest-path",
     "manylinux",
+    "profile",
     "rustc-extra-args",
     "skip-auditwheel",
     "strip",
+    "variant",
 ]
 
 
@@ -54,21 +59,24 @@ def get_config_options() -> List[str]:
 
 # noinspection PyUnusedLocal
 def build_wheel(wheel_directory, config_settings=None, metadata_directory=None):
-    # The PEP 517 build environment guarantees that `python` is the correct python
-    command = ["maturin", "pep517", "build-wheel", "-i", "python"]
-    command.extend(get_config_options())
-
-    print("Running `{}`".format(" ".join(command)))
-    try:
-        output = subprocess.check_output(command)
-    except subprocess.CalledProcessError as e:
-        print("Error: {}".format(e))
-        sys.exit(1)
-    sys.stdout.buffer.write(output)
-    sys.stdout.flush()
-    output = output.decode(errors="replace")
-    filename = output.strip().splitlines()[-1]
-    shutil.copy2("target/wheels/" + filename, os.path.join(wheel_directory, filename))
+    # The wheel goes to a temporary directory since the target directory isn't necessarily
+    # target/ in the current directory, e.g. for a member of a workspace
+    with tempfile.TemporaryDirectory() as out:
+        # The PEP 517 build environment guarantees that `python` is the correct python
+        command = ["maturin", "pep517", "build-wheel", "-i", "python", "--out", out]
+        command.extend(get_config_options())
+
+        print("Running `{}`".format(" ".join(command)))
+        try:
+            output = subprocess.check_output(command)
+        except subprocess.CalledProcessError as e:
+            print("Error: {}".format(e))
+            sys.exit(1)
+        sys.stdout.buffer.write(output)
+        sys.stdout.flush()
+        output = output.decode(errors="replace")
+        filename = output.strip().splitlines()[-1]
+        shutil.copy2(os.path.join(out, filename), os.path.join(wheel_directory, filename))
     return filename
 
 
@@ -0,0 +1,259 @@
+//! Audits wheels that have already been built, possibly by other tools, with the same checks that
+//! are run on freshly compiled libraries
+
+use crate::auditwheel::{check_architecture, find_external_libraries, find_too_new_symbols};
+use crate::module_writer::expand_compressed_tag;
+use crate::{Manylinux, Target};
+use anyhow::{bail, format_err, Context, Result};
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use zip::ZipArchive;
+
+/// The result of auditing a single elf file in the wheel
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct FileAudit {
+    /// The path of the file inside the wheel
+    pub path: String,
+    /// The platform tag of the policy the file was checked against
+    pub policy: String,
+    /// The libraries the file links which aren't allowed by the policy
+    pub external_libraries: Vec<String>,
+    /// The versioned symbols the file references which are too recent for the policy
+    pub too_new_symbols: Vec<String>,
+}
+
+impl FileAudit {
+    /// Whether the file complies with the policy
+    pub fn is_compliant(&self) -> bool {
+        self.external_libraries.is_empty() && self.too_new_symbols.is_empty()
+    }
+}
+
+/// The result of auditing all elf files in a wheel against the platform tags in its WHEEL file
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct WheelAudit {
+    /// The path to the wheel
+    pub wheel: PathBuf,
+    /// The platform tags declared in the WHEEL file
+    pub platform_tags: Vec<String>,
+    /// One entry per elf file and policy
+    pub files: Vec<FileAudit>,
+}
+
+impl WheelAudit {
+    /// Whether all elf files comply with all declared policies
+    pub fn is_compliant(&self) -> bool {
+        self.files.iter().all(FileAudit::is_compliant)
+    }
+
+    /// Prints the report for humans
+    pub fn print(&self) {
+        println!(
+            "🔍 Auditing {} against {}",
+            self.wheel.display(),
+            self.platform_tags.join(", ")
+        );
+        if self.files.is_empty() {
+            println!("   The wheel contains no shared libraries or executables");
+        }
+        for file in &self.files {
+            if file.is_compliant() {
+                println!("   ✔ {} ({})", file.path, file.policy);
+                continue;
+            }
+            println!("   ✘ {} ({}):", file.path, file.policy);
+            for library in &file.external_libraries {
+                println!("      - links {}, which isn't allowed", library);
+            }
+            for symbol in &file.too_new_symbols {
+                println!("      - references the too recent symbol {}", symbol);
+            }
+        }
+    }
+}
+
+/// Parses a linux platform tag such as `manylinux_2_17_x86_64`, `manylinux2010_i686`,
+/// `musllinux_1_1_aarch64` or `linux_x86_64` into the policy and the target it stands for
+///
+/// Returns `None` for platform tags of other operating systems
+fn parse_platform_tag(platform: &str) -> Result<Option<(Manylinux, Target)>> {
+    let (policy, arch, musl) =
+        if platform.starts_with("manylinux_") || platform.starts_with("musllinux_") {
+            // PEP 600 and PEP 656: <name>_<major>_<minor>_<arch>, where arch may contain underscores
+            let parts: Vec<&str> = platform.splitn(4, '_').collect();
+            if parts.len() != 4 {
+                bail!("Invalid platform tag {}", platform);
+            }
+            let policy = format!("{}_{}_{}", parts[0], parts[1], parts[2]);
+            let policy = if parts[0] == "manylinux" {
+                // `Manylinux::from_str` expects the glibc version only, e.g. `2_17`
+                policy.trim_start_matches("manylinux_").to_string()
+            } else {
+                policy
+            };
+            (policy, parts[3], parts[0] == "musllinux")
+        } else if platform.starts_with("manylinux") || platform.starts_with("linux_") {
+            // The legacy tags: manylinux1, manylinux2010, manylinux2014 and the native linux tag
+            let mut parts = platform.splitn(2, '_');
+            let policy = parts.next().unwrap();
+            let arch = parts
+                .next()
+                .ok_or_else(|| format_err!("Invalid platform tag {}", platform))?;
+            let policy = match policy {
+                "linux" => "off",
+                other => other.trim_start_matches("manylinux"),
+            };
+            (policy.to_string(), arch, false)
+        } else {
+            return Ok(None);
+        };
+
+    let manylinux = Manylinux::from_str(&policy)
+        .map_err(|_| format_err!("Unknown policy in the platform tag {}", platform))?;
+    let libc = if musl { "musl" } else { "gnu" };
+    let triple = match arch {
+        "x86_64" => format!("x86_64-unknown-linux-{}", libc),
+        "i686" => format!("i686-unknown-linux-{}", libc),
+        "aarch64" => format!("aarch64-unknown-linux-{}", libc),
+        "armv7l" | "arm7l" => format!("armv7-unknown-linux-{}eabihf", libc),
+        _ => bail!("Unsupported architecture {} in the platform tag", arch),
+    };
+    Ok(Some((manylinux, Target::from_target_triple(Some(triple))?)))
+}
+
+/// Reads the platform tags from the WHEEL file of the wheel
+fn read_platform_tags(archive: &mut ZipArchive<File>) -> Result<Vec<String>> {
+    let wheel_file = (0..archive.len())
+        .map(|index| archive.by_index(index).map(|file| file.name().to_string()))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .find(
+            |name| match name.split('/').collect::<Vec<_>>().as_slice() {
+                [dir, "WHEEL"] => dir.ends_with(".dist-info"),
+                _ => false,
+            },
+        )
+        .ok_or_else(|| format_err!("The wheel doesn't contain a .dist-info/WHEEL file"))?;
+
+    let mut contents = String::new();
+    archive
+        .by_name(&wheel_file)?
+        .read_to_string(&mut contents)
+        .context("Failed to read the WHEEL file")?;
+
+    let mut platform_tags = Vec::new();
+    for line in contents.lines() {
+        if !line.starts_with("Tag:") {
+            continue;
+        }
+        for tag in expand_compressed_tag(line.trim_start_matches("Tag:").trim()) {
+            let platform = tag.splitn(3, '-').nth(2).unwrap_or_default().to_string();
+            if !platform_tags.contains(&platform) {
+                platform_tags.push(platform);
+            }
+        }
+    }
+    Ok(platform_tags)
+}
+
+/// Checks every elf file in the wheel, including the ones shipped in the python part of mixed
+/// projects, against each linux policy declared through the tags in the WHEEL file, running the
+/// same checks as [auditwheel_rs](crate::auditwheel_rs)
+pub fn audit_wheel(wheel: &Path) -> Result<WheelAudit> {
+    let file = File::open(wheel).context(format!("Failed to open {}", wheel.display()))?;
+    let mut archive =
+        ZipArchive::new(file).context(format!("{} is not a valid wheel", wheel.display()))?;
+
+    let platform_tags = read_platform_tags(&mut archive)?;
+    // The legacy and the PEP 600 name of a policy are parsed to the same value, so we only check
+    // each policy once
+    let mut policies: Vec<(String, Manylinux, Target)> = Vec::new();
+    for platform in &platform_tags {
+        if let Some((manylinux, target)) = parse_platform_tag(platform)? {
+            if !policies
+                .iter()
+                .any(|(_, other, other_target)| other == &manylinux && other_target == &target)
+            {
+                policies.push((platform.clone(), manylinux, target));
+            }
+        }
+    }
+    if policies.is_empty() {
+        bail!(
+            "{} is not a linux wheel, its platform tags are {}",
+            wheel.display(),
+            platform_tags.join(", ")
+        );
+    }
+
+    let tempdir = tempfile::tempdir()?;
+    let mut files = Vec::new();
+    for index in 0..archive.len() {
+        let mut zip_file = archive.by_index(index)?;
+        if zip_file.is_dir() {
+            continue;
+        }
+        let mut contents = Vec::new();
+        zip_file
+            .read_to_end(&mut contents)
+            .context(format!("Failed to read {} from the wheel", zip_file.name()))?;
+        if !contents.starts_with(b"\x7fELF") {
+            continue;
+        }
+
+        // The checks work on files, so the elf file is extracted first
+        let extracted = tempdir.path().join(index.to_string());
+        fs::write(&extracted, &contents)?;
+        for (platform, manylinux, target) in &policies {
+            check_architecture(&extracted, target).context(format!(
+                "{} doesn't match {}",
+                zip_file.name(),
+                platform
+            ))?;
+            files.push(FileAudit {
+                path: zip_file.name().to_string(),
+                policy: platform.clone(),
+                external_libraries: find_external_libraries(&extracted, target, manylinux)?,
+                too_new_symbols: find_too_new_symbols(&extracted, target, manylinux)?,
+            });
+        }
+    }
+
+    Ok(WheelAudit {
+        wheel: wheel.to_path_buf(),
+        platform_tags,
+        files,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_platform_tag() {
+        let (manylinux, target) = parse_platform_tag("manylinux_2_17_x86_64")
+            .unwrap()
+            .unwrap();
+        assert_eq!(manylinux, Manylinux::Manylinux2014);
+        assert_eq!(target.get_platform_arch(), "x86_64");
+
+        let (legacy, _) = parse_platform_tag("manylinux2014_x86_64").unwrap().unwrap();
+        assert_eq!(legacy, manylinux);
+
+        let (manylinux, target) = parse_platform_tag("musllinux_1_2_aarch64")
+            .unwrap()
+            .unwrap();
+        assert_eq!(manylinux, Manylinux::Musllinux(1, 2));
+        assert!(target.is_musl_libc());
+
+        let (manylinux, _) = parse_platform_tag("linux_i686").unwrap().unwrap();
+        assert_eq!(manylinux, Manylinux::Off);
+
+        assert!(parse_platform_tag("win_amd64").unwrap().is_none());
+        assert!(parse_platform_tag("manylinux_2_x86_64").is_err());
+    }
+}
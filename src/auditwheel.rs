@@ -1,64 +1,114 @@
+use crate::repair::find_vendored_libraries;
 use crate::Manylinux;
 use crate::Target;
 use anyhow::Result;
+use goblin::elf::dynamic::{DT_RPATH, DT_RUNPATH};
+use goblin::elf::header::machine_to_str;
+use goblin::elf::section_header::{SHT_GNU_VERNEED, SHT_GNU_VERSYM};
 use goblin::elf::Elf;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io;
 use std::io::Read;
 use std::path::Path;
 use thiserror::Error;
-/// As specified in "PEP 513 -- A Platform Tag for Portable Linux Built
-/// Distributions"
-const MANYLINUX1: &[&str] = &[
-    "libpanelw.so.5",
-    "libncursesw.so.5",
-    "libgcc_s.so.1",
-    "libstdc++.so.6",
-    "libm.so.6",
-    "libdl.so.2",
-    "librt.so.1",
-    "libcrypt.so.1",
-    "libc.so.6",
-    "libnsl.so.1",
-    "libutil.so.1",
-    "libpthread.so.0",
-    "libresolv.so.2",
-    "libX11.so.6",
-    "libXext.so.6",
-    "libXrender.so.1",
-    "libICE.so.6",
-    "libSM.so.6",
-    "libGL.so.1",
-    "libgobject-2.0.so.0",
-    "libgthread-2.0.so.0",
-    "libglib-2.0.so.0",
-];
-
-/// As specified in "PEP 571 -- The manylinux2010 Platform Tag"
-///
-/// Currently unused since the python ecosystem is still on manylinux 1
-const MANYLINUX2010: &[&str] = &[
-    "libgcc_s.so.1",
-    "libstdc++.so.6",
-    "libm.so.6",
-    "libdl.so.2",
-    "librt.so.1",
-    "libcrypt.so.1",
-    "libc.so.6",
-    "libnsl.so.1",
-    "libutil.so.1",
-    "libpthread.so.0",
-    "libresolv.so.2",
-    "libX11.so.6",
-    "libXext.so.6",
-    "libXrender.so.1",
-    "libICE.so.6",
-    "libSM.so.6",
-    "libGL.so.1",
-    "libgobject-2.0.so.0",
-    "libgthread-2.0.so.0",
-    "libglib-2.0.so.0",
-];
+/// The manylinux policies in the style of auditwheel's policy.json, ordered from the oldest to
+/// the most recent glibc
+const MANYLINUX_POLICY: &str = include_str!("manylinux-policy.json");
+
+/// The musllinux policies in the same format, ordered from the oldest to the most recent musl.
+/// musl has no symbol versioning, so there are no symbol versions to check.
+const MUSLLINUX_POLICY: &str = include_str!("musllinux-policy.json");
+
+lazy_static! {
+    static ref MANYLINUX_POLICIES: Vec<Policy> =
+        serde_json::from_str(MANYLINUX_POLICY).expect("The embedded manylinux policy is invalid");
+    static ref MUSLLINUX_POLICIES: Vec<Policy> =
+        serde_json::from_str(MUSLLINUX_POLICY).expect("The embedded musllinux policy is invalid");
+}
+
+/// A manylinux policy as specified in PEP 513 (manylinux1), PEP 571 (manylinux2010),
+/// PEP 599 (manylinux2014) or PEP 600 (manylinux_x_y), or a musllinux policy as specified in
+/// PEP 656 (musllinux_x_y)
+#[derive(Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct Policy {
+    /// The PEP 600 or PEP 656 name, e.g. `manylinux_2_17` or `musllinux_1_1`
+    pub name: String,
+    /// The legacy names, e.g. `manylinux2014`
+    pub aliases: Vec<String>,
+    /// The libraries an elf file may link, by architecture
+    pub lib_whitelist: HashMap<String, Vec<String>>,
+    /// The most recent versions of the versioned symbols (e.g. `GLIBC` => `2.17` allows
+    /// `memcpy@GLIBC_2.14`) an elf file may reference, by architecture
+    pub symbol_versions: HashMap<String, HashMap<String, String>>,
+}
+
+impl Policy {
+    /// Returns all known policies for the libc of the target, ordered from the oldest to the most
+    /// recent libc version
+    pub fn all(target: &Target) -> &'static [Policy] {
+        if target.is_musl_libc() {
+            &MUSLLINUX_POLICIES
+        } else {
+            &MANYLINUX_POLICIES
+        }
+    }
+
+    /// Returns the policy for the manylinux setting if it's checked. For musl targets, the
+    /// setting is mapped to the equivalent musllinux policy.
+    ///
+    /// Fails if there's no policy for the libc version or the architecture of the target
+    pub fn for_target(
+        target: &Target,
+        manylinux: &Manylinux,
+    ) -> Result<Option<Policy>, AuditWheelError> {
+        let manylinux = if target.is_musl_libc() {
+            manylinux.for_musl()
+        } else {
+            manylinux.clone()
+        };
+        if !manylinux.is_checked() {
+            return Ok(None);
+        }
+        let name = match (manylinux.glibc_version(), manylinux.musl_version()) {
+            (Some((major, minor)), _) => format!("manylinux_{}_{}", major, minor),
+            (_, Some((major, minor))) => format!("musllinux_{}_{}", major, minor),
+            (None, None) => return Ok(None),
+        };
+        let arch = target.get_platform_arch();
+        Policy::all(target)
+            .iter()
+            .find(|policy| policy.name == name && policy.lib_whitelist.contains_key(&arch))
+            .cloned()
+            .map(Some)
+            .ok_or(AuditWheelError::UnknownPolicyError(name, arch))
+    }
+
+    /// Returns the libc version from the name, e.g. `(2, 17)` for `manylinux_2_17`
+    pub fn libc_version(&self) -> (u16, u16) {
+        let mut parts = self.name.rsplitn(3, '_');
+        let mut next = || {
+            parts
+                .next()
+                .and_then(|part| part.parse().ok())
+                .expect("The embedded manylinux policy has an invalid name")
+        };
+        let minor = next();
+        (next(), minor)
+    }
+
+    /// Returns the manylinux setting that checks for compliance with this policy
+    pub fn to_manylinux(&self) -> Manylinux {
+        let (major, minor) = self.libc_version();
+        if self.name.starts_with("musllinux_") {
+            Manylinux::Musllinux(major, minor)
+        } else {
+            Manylinux::from_glibc_version(major, minor, true)
+        }
+    }
+}
 
 /// Error raised during auditing an elf file for manylinux compatibility
 #[derive(Error, Debug)]
@@ -76,50 +126,702 @@ pub enum AuditWheelError {
         "Your library is not manylinux compliant because it links the following forbidden libraries: {0:?}",
     )]
     ManylinuxValidationError(Vec<String>),
+    /// The elf file references versioned symbols that are too recent for the manylinux
+    /// policy, e.g. `memcpy@GLIBC_2.14` for manylinux2010. Contains the list of offending
+    /// symbols.
+    #[error(
+        "Your library is not manylinux compliant because it references the following too recent versioned symbols: {0:?}",
+    )]
+    VersionedSymbolTooNewError(Vec<String>),
+    /// There's no policy for the requested glibc version and architecture. Contains the
+    /// name of the policy and the architecture.
+    #[error(
+        "There is no known {0} policy for {1}. Use the unchecked variant to skip the compliance check",
+    )]
+    UnknownPolicyError(String, String),
+    /// The elf file was built for another architecture than the target, e.g. an x86_64 library
+    /// in an aarch64 wheel. Contains the architecture of the file and the one of the target.
+    #[error(
+        "The elf file was built for {0}, which doesn't match the target architecture {1}. Did you forget to pass --target?",
+    )]
+    ArchitectureMismatchError(String, String),
+    /// A file that was added to the wheel isn't manylinux compliant. Contains the path of the
+    /// file in the wheel and the reason.
+    #[error("{0} in the wheel is not manylinux compliant")]
+    WheelFileError(String, #[source] Box<AuditWheelError>),
+    /// A library that `--repair` would copy into the wheel isn't manylinux compliant or
+    /// couldn't be found. Contains the name of the library and the reason.
+    #[error(
+        "The library {0}, which would be copied into the wheel, is not manylinux compliant: {1}"
+    )]
+    VendoredLibraryError(String, String),
 }
 
-/// An (incomplete) reimplementation of auditwheel, which checks elf files for
-/// manylinux compliance. Returns an error for non compliant elf files
+/// Reads a u16 with the endianness of the elf file
+fn read_u16(bytes: &[u8], offset: usize, little_endian: bool) -> Option<u16> {
+    let mut buf = [0; 2];
+    buf.copy_from_slice(bytes.get(offset..offset + 2)?);
+    if little_endian {
+        Some(u16::from_le_bytes(buf))
+    } else {
+        Some(u16::from_be_bytes(buf))
+    }
+}
+
+/// Reads a u32 with the endianness of the elf file
+fn read_u32(bytes: &[u8], offset: usize, little_endian: bool) -> Option<u32> {
+    let mut buf = [0; 4];
+    buf.copy_from_slice(bytes.get(offset..offset + 4)?);
+    if little_endian {
+        Some(u32::from_le_bytes(buf))
+    } else {
+        Some(u32::from_be_bytes(buf))
+    }
+}
+
+/// Parses the `.gnu.version_r` section, which lists the symbol versions required from each of
+/// the needed libraries. Returns a mapping from the version index to the version name,
+/// e.g. `GLIBC_2.14`.
 ///
-/// Only checks for the libraries marked as NEEDED, but not for symbol versions
-/// (e.g. requiring a too recent glibc isn't caught).
-pub fn auditwheel_rs(
-    path: &Path,
-    target: &Target,
-    manylinux: &Manylinux,
-) -> Result<(), AuditWheelError> {
-    if !target.is_linux() {
-        return Ok(());
+/// The layout of Elf32_Verneed/Elf64_Verneed and Elf32_Vernaux/Elf64_Vernaux is identical.
+fn parse_verneed(elf: &Elf, buffer: &[u8]) -> HashMap<u16, String> {
+    let mut versions = HashMap::new();
+    let le = elf.little_endian;
+    let section = match elf
+        .section_headers
+        .iter()
+        .find(|header| header.sh_type == SHT_GNU_VERNEED)
+    {
+        Some(section) => section,
+        None => return versions,
+    };
+
+    let mut offset = section.sh_offset as usize;
+    for _ in 0..section.sh_info {
+        let (vn_cnt, vn_aux, vn_next) = match (
+            read_u16(buffer, offset + 2, le),
+            read_u32(buffer, offset + 8, le),
+            read_u32(buffer, offset + 12, le),
+        ) {
+            (Some(vn_cnt), Some(vn_aux), Some(vn_next)) => (vn_cnt, vn_aux, vn_next),
+            _ => break,
+        };
+
+        let mut aux_offset = offset + vn_aux as usize;
+        for _ in 0..vn_cnt {
+            let (vna_other, vna_name, vna_next) = match (
+                read_u16(buffer, aux_offset + 6, le),
+                read_u32(buffer, aux_offset + 8, le),
+                read_u32(buffer, aux_offset + 12, le),
+            ) {
+                (Some(vna_other), Some(vna_name), Some(vna_next)) => {
+                    (vna_other, vna_name, vna_next)
+                }
+                _ => break,
+            };
+            if let Some(Ok(name)) = elf.dynstrtab.get(vna_name as usize) {
+                versions.insert(vna_other, name.to_string());
+            }
+            if vna_next == 0 {
+                break;
+            }
+            aux_offset += vna_next as usize;
+        }
+
+        if vn_next == 0 {
+            break;
+        }
+        offset += vn_next as usize;
+    }
+
+    versions
+}
+
+/// Returns all references to versioned symbols as `symbol@VERSION`, e.g. `memcpy@GLIBC_2.14`,
+/// paired with the version name.
+///
+/// If there is no `.gnu.version` section mapping the dynamic symbols to their versions, only the
+/// required version names are returned.
+fn find_versioned_symbols(elf: &Elf, buffer: &[u8]) -> Vec<(String, String)> {
+    let versions = parse_verneed(elf, buffer);
+    if versions.is_empty() {
+        return Vec::new();
+    }
+
+    let versym = elf
+        .section_headers
+        .iter()
+        .find(|header| header.sh_type == SHT_GNU_VERSYM);
+
+    let versym = match versym {
+        Some(versym) => versym,
+        None => {
+            return versions
+                .values()
+                .map(|version| (version.clone(), version.clone()))
+                .collect();
+        }
+    };
+
+    let mut symbols = Vec::new();
+    for (index, sym) in elf.dynsyms.iter().enumerate() {
+        let entry_offset = versym.sh_offset as usize + index * 2;
+        // The highest bit marks hidden symbols
+        let version_index = match read_u16(buffer, entry_offset, elf.little_endian) {
+            Some(version_index) => version_index & 0x7fff,
+            None => break,
+        };
+        if let Some(version) = versions.get(&version_index) {
+            let name = elf.dynstrtab.get(sym.st_name).and_then(Result::ok);
+            if let Some(name) = name {
+                symbols.push((format!("{}@{}", name, version), version.clone()));
+            }
+        }
     }
-    let reference: &[&str];
-    match *manylinux {
-        Manylinux::Manylinux1 => reference = MANYLINUX1,
-        Manylinux::Manylinux2010 => reference = MANYLINUX2010,
-        _ => return Ok(()),
+
+    symbols
+}
+
+/// Splits a dotted version such as `2.14` or `3.4.8` into its numeric parts. Returns `None`
+/// for non-numeric versions such as `GLIBC_PRIVATE`
+fn parse_symbol_version(version: &str) -> Option<Vec<u32>> {
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// Returns true if the symbol version (e.g. `GLIBC_2.14`) is not allowed by the given list of
+/// maximum versions. Versions of libraries without a limit are always allowed.
+///
+/// The version number follows the last `_`, since the library part may contain underscores
+/// itself, e.g. `CXXABI_TM_1` is version `1` of `CXXABI_TM` and not version `TM_1` of `CXXABI`
+fn is_symbol_version_too_new(version: &str, max_versions: &HashMap<String, String>) -> bool {
+    let (prefix, number) = match version.rfind('_') {
+        Some(pos) => (&version[..pos], &version[pos + 1..]),
+        None => return false,
+    };
+    let max_version = match max_versions.get(prefix) {
+        Some(max_version) => max_version,
+        None => return false,
     };
+    match (
+        parse_symbol_version(number),
+        parse_symbol_version(max_version),
+    ) {
+        (Some(number), Some(max_version)) => number > max_version,
+        // e.g. GLIBC_PRIVATE
+        _ => true,
+    }
+}
+
+/// Reads the whole elf file into memory
+fn read_elf_file(path: &Path) -> Result<Vec<u8>, AuditWheelError> {
     let mut file = File::open(path).map_err(AuditWheelError::IOError)?;
     let mut buffer = Vec::new();
     file.read_to_end(&mut buffer)
         .map_err(AuditWheelError::IOError)?;
+    Ok(buffer)
+}
+
+/// Checks that the machine type and the class (32 or 64 bit) of the elf file match the target,
+/// so that e.g. a cross compiled library that ended up in a wheel for the host fails early
+fn check_elf_architecture(elf: &Elf, target: &Target) -> Result<(), AuditWheelError> {
+    let expected_bits = target.pointer_width();
+    let bits = if elf.is_64 { 64 } else { 32 };
+    if elf.header.e_machine != target.get_elf_machine() || bits != expected_bits {
+        return Err(AuditWheelError::ArchitectureMismatchError(
+            format!("{} ({}-bit)", machine_to_str(elf.header.e_machine), bits),
+            format!("{} ({}-bit)", target.get_platform_arch(), expected_bits),
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that the elf file was built for the architecture of the target
+pub fn check_architecture(path: &Path, target: &Target) -> Result<(), AuditWheelError> {
+    let buffer = read_elf_file(path)?;
     let elf = Elf::parse(&buffer).map_err(AuditWheelError::GoblinError)?;
+    check_elf_architecture(&elf, target)
+}
+
+/// Returns the libraries marked as NEEDED in the elf file which aren't allowed by the manylinux
+/// policy
+pub fn find_external_libraries(
+    path: &Path,
+    target: &Target,
+    manylinux: &Manylinux,
+) -> Result<Vec<String>, AuditWheelError> {
+    find_external_libraries_in(&read_elf_file(path)?, target, manylinux)
+}
+
+/// [find_external_libraries] for an elf file that has already been read into memory
+fn find_external_libraries_in(
+    buffer: &[u8],
+    target: &Target,
+    manylinux: &Manylinux,
+) -> Result<Vec<String>, AuditWheelError> {
+    let policy = match Policy::for_target(target, manylinux)? {
+        Some(policy) => policy,
+        None => return Ok(Vec::new()),
+    };
+    let reference = &policy.lib_whitelist[&target.get_platform_arch()];
+    let elf = Elf::parse(buffer).map_err(AuditWheelError::GoblinError)?;
+    check_elf_architecture(&elf, target)?;
     // This returns essentially the same as ldd
     let deps: Vec<String> = elf.libraries.iter().map(ToString::to_string).collect();
 
     let mut offenders = Vec::new();
     for dep in deps {
-        // I'm not 100% what exactely this line does, but auditwheel also seems to skip
-        // everything with ld-linux in its name
-        if dep == "ld-linux-x86-64.so.2" || dep == "ld-linux.so.2" {
+        // The dynamic loader is part of the libc and therefore always available, but it isn't
+        // listed in the policies
+        if dep == target.get_dynamic_loader() {
             continue;
         }
-        if !reference.contains(&dep.as_str()) {
+        if !reference.contains(&dep) {
             offenders.push(dep);
         }
     }
+    Ok(offenders)
+}
+
+/// Returns the versioned symbols from glibc, libstdc++ or libgcc the elf file references which
+/// are too recent for the manylinux policy, e.g. `memcpy@GLIBC_2.14` for manylinux1
+pub fn find_too_new_symbols(
+    path: &Path,
+    target: &Target,
+    manylinux: &Manylinux,
+) -> Result<Vec<String>, AuditWheelError> {
+    find_too_new_symbols_in(&read_elf_file(path)?, target, manylinux)
+}
+
+/// [find_too_new_symbols] for an elf file that has already been read into memory
+fn find_too_new_symbols_in(
+    buffer: &[u8],
+    target: &Target,
+    manylinux: &Manylinux,
+) -> Result<Vec<String>, AuditWheelError> {
+    let policy = match Policy::for_target(target, manylinux)? {
+        Some(policy) => policy,
+        None => return Ok(Vec::new()),
+    };
+    let max_versions = &policy.symbol_versions[&target.get_platform_arch()];
+    let elf = Elf::parse(buffer).map_err(AuditWheelError::GoblinError)?;
 
-    if offenders.is_empty() {
+    let mut too_new: Vec<String> = find_versioned_symbols(&elf, buffer)
+        .into_iter()
+        .filter(|(_, version)| is_symbol_version_too_new(version, max_versions))
+        .map(|(symbol, _)| symbol)
+        .collect();
+    too_new.sort();
+    too_new.dedup();
+    Ok(too_new)
+}
+
+/// Checks that the elf file doesn't reference versioned symbols from glibc, libstdc++ or libgcc
+/// that are too recent for the manylinux policy
+pub fn check_symbol_versions(
+    path: &Path,
+    target: &Target,
+    manylinux: &Manylinux,
+) -> Result<(), AuditWheelError> {
+    let too_new = find_too_new_symbols(path, target, manylinux)?;
+    if too_new.is_empty() {
         Ok(())
     } else {
-        Err(AuditWheelError::ManylinuxValidationError(offenders))
+        Err(AuditWheelError::VersionedSymbolTooNewError(too_new))
+    }
+}
+
+/// An (incomplete) reimplementation of auditwheel, which checks elf files for
+/// manylinux compliance. Returns an error for non compliant elf files
+///
+/// Checks both the libraries marked as NEEDED and the versions of the symbols referenced from
+/// glibc, libstdc++ and libgcc (e.g. requiring a too recent glibc).
+pub fn auditwheel_rs(
+    path: &Path,
+    target: &Target,
+    manylinux: &Manylinux,
+) -> Result<(), AuditWheelError> {
+    if !target.is_linux() {
+        return Ok(());
+    }
+
+    check_architecture(path, target)?;
+    let offenders = find_external_libraries(path, target, manylinux)?;
+    if !offenders.is_empty() {
+        return Err(AuditWheelError::ManylinuxValidationError(offenders));
+    }
+
+    check_symbol_versions(path, target, manylinux)
+}
+
+/// Resolves a RUNPATH or RPATH entry of the elf file in the directory `origin` of the wheel to a
+/// directory in the wheel, e.g. `$ORIGIN/../foo.libs` in `foo` to `foo.libs`. Returns `None` for
+/// entries that aren't relative to `$ORIGIN` or point outside of the wheel.
+fn resolve_origin(origin: &str, entry: &str) -> Option<String> {
+    let relative = entry
+        .strip_prefix("$ORIGIN")
+        .or_else(|| entry.strip_prefix("${ORIGIN}"))?;
+    if !relative.is_empty() && !relative.starts_with('/') {
+        return None;
+    }
+
+    let mut dir: Vec<&str> = Vec::new();
+    for component in origin.split('/').chain(relative.split('/')) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                dir.pop()?;
+            }
+            component => dir.push(component),
+        }
+    }
+    Some(dir.join("/"))
+}
+
+/// Returns the directories in the wheel the `$ORIGIN` relative RUNPATH and RPATH entries of the
+/// elf file at `path` in the wheel point to
+fn origin_runpaths(elf: &Elf, path: &str) -> Vec<String> {
+    let origin = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let mut dirs = Vec::new();
+    if let Some(ref dynamic) = elf.dynamic {
+        for dyn_ in &dynamic.dyns {
+            if dyn_.d_tag != DT_RPATH && dyn_.d_tag != DT_RUNPATH {
+                continue;
+            }
+            if let Some(Ok(value)) = elf.dynstrtab.get(dyn_.d_val as usize) {
+                dirs.extend(
+                    value
+                        .split(':')
+                        .filter_map(|entry| resolve_origin(origin, entry)),
+                );
+            }
+        }
+    }
+    dirs
+}
+
+/// Checks all elf files that were added to a wheel, given as their path in the wheel and the
+/// file with their contents. Linking a library is allowed if the wheel ships it in a directory
+/// that is in an `$ORIGIN` relative RUNPATH of the elf file, e.g. because it was vendored by
+/// `--repair`, since only then the dynamic linker finds it after the wheel was installed.
+pub fn auditwheel_files(
+    files: &[(&str, &Path)],
+    target: &Target,
+    manylinux: &Manylinux,
+) -> Result<(), AuditWheelError> {
+    if !target.is_linux() {
+        return Ok(());
+    }
+
+    let shipped: HashSet<&str> = files.iter().map(|(path, _)| *path).collect();
+    for (path, source) in files {
+        let audit = || {
+            let contents = &read_elf_file(source)?;
+            let elf = Elf::parse(contents).map_err(AuditWheelError::GoblinError)?;
+            check_elf_architecture(&elf, target)?;
+            let runpaths = origin_runpaths(&elf, path);
+            let offenders: Vec<String> = find_external_libraries_in(contents, target, manylinux)?
+                .into_iter()
+                .filter(|library| {
+                    !runpaths.iter().any(|dir| {
+                        let library = if dir.is_empty() {
+                            library.clone()
+                        } else {
+                            format!("{}/{}", dir, library)
+                        };
+                        shipped.contains(library.as_str())
+                    })
+                })
+                .collect();
+            if !offenders.is_empty() {
+                return Err(AuditWheelError::ManylinuxValidationError(offenders));
+            }
+            let too_new = find_too_new_symbols_in(contents, target, manylinux)?;
+            if !too_new.is_empty() {
+                return Err(AuditWheelError::VersionedSymbolTooNewError(too_new));
+            }
+            Ok(())
+        };
+        audit().map_err(|err| AuditWheelError::WheelFileError(path.to_string(), Box::new(err)))?;
+    }
+    Ok(())
+}
+
+/// Checks the symbol versions of the elf file and of the external libraries `--repair` would
+/// vendor into the wheel for the policy, which have to comply with the policy too
+fn check_repairable(
+    path: &Path,
+    target: &Target,
+    manylinux: &Manylinux,
+) -> Result<(), AuditWheelError> {
+    check_symbol_versions(path, target, manylinux)?;
+    let vendored = find_vendored_libraries(path, target, manylinux).map_err(|err| {
+        AuditWheelError::VendoredLibraryError(path.display().to_string(), format!("{:#}", err))
+    })?;
+    for (name, library) in vendored {
+        check_symbol_versions(&library, target, manylinux)
+            .map_err(|err| AuditWheelError::VendoredLibraryError(name, err.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Returns the oldest manylinux policy the elf file complies with, i.e. the one that makes the
+/// wheel installable on the most systems. If the external libraries are going to be vendored
+/// into the wheel, they are allowed, but the symbol versions of the vendored libraries are
+/// checked as well.
+///
+/// Fails with the error for the most recent policy if the file complies with none of them
+pub fn find_oldest_policy(
+    path: &Path,
+    target: &Target,
+    repair: bool,
+) -> Result<Manylinux, AuditWheelError> {
+    if !target.is_linux() {
+        return Ok(Manylinux::Off);
+    }
+
+    check_architecture(path, target)?;
+    let arch = target.get_platform_arch();
+    let mut last_error = None;
+    for policy in Policy::all(target) {
+        if !policy.lib_whitelist.contains_key(&arch) {
+            continue;
+        }
+        let manylinux = policy.to_manylinux();
+        let result = if repair {
+            check_repairable(path, target, &manylinux)
+        } else {
+            auditwheel_rs(path, target, &manylinux)
+        };
+        match result {
+            Ok(()) => return Ok(manylinux),
+            Err(err) => last_error = Some(err),
+        }
+    }
+    Err(last_error
+        .unwrap_or_else(|| AuditWheelError::UnknownPolicyError("manylinux".to_string(), arch)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    fn target(triple: &str) -> Target {
+        Target::from_target_triple(Some(triple.to_string())).unwrap()
+    }
+
+    fn max_versions(name: &str) -> HashMap<String, String> {
+        Policy::all(&target("x86_64-unknown-linux-gnu"))
+            .iter()
+            .find(|policy| policy.name == name)
+            .unwrap()
+            .symbol_versions["x86_64"]
+            .clone()
+    }
+
+    #[test]
+    fn test_policies() {
+        for (triple, expected) in &[
+            (
+                "x86_64-unknown-linux-gnu",
+                vec![(2, 5), (2, 12), (2, 17), (2, 24)],
+            ),
+            ("x86_64-unknown-linux-musl", vec![(1, 1), (1, 2)]),
+        ] {
+            let policies = Policy::all(&target(triple));
+            let versions: Vec<(u16, u16)> = policies.iter().map(Policy::libc_version).collect();
+            assert_eq!(&versions, expected);
+            let mut sorted = versions.clone();
+            sorted.sort();
+            assert_eq!(
+                versions, sorted,
+                "The policies must be ordered by libc version"
+            );
+            for policy in policies {
+                for arch in policy.lib_whitelist.keys() {
+                    assert!(policy.symbol_versions.contains_key(arch));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_musl_policy_for_target() {
+        let musl = target("x86_64-unknown-linux-musl");
+        let policy = Policy::for_target(&musl, &Manylinux::Manylinux1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(policy.name, "musllinux_1_1");
+        assert_eq!(policy.to_manylinux(), Manylinux::Musllinux(1, 1));
+        assert!(Policy::for_target(&musl, &Manylinux::Manylinux1Unchecked)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+    fn test_auditwheel_files() {
+        // The test binary is built against the glibc of the build host, which is more recent
+        // than the one of manylinux1
+        let test_binary = std::env::current_exe().unwrap();
+        let files = vec![("foo/test.so", test_binary.as_path())];
+        let target = target("x86_64-unknown-linux-gnu");
+        match auditwheel_files(&files, &target, &Manylinux::Manylinux1) {
+            Err(AuditWheelError::WheelFileError(path, _)) => assert_eq!(path, "foo/test.so"),
+            other => panic!("Expected a WheelFileError, got {:?}", other),
+        }
+        auditwheel_files(&files, &target, &Manylinux::Manylinux1Unchecked).unwrap();
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+    fn test_check_architecture() {
+        let test_binary = std::env::current_exe().unwrap();
+        check_architecture(&test_binary, &target("x86_64-unknown-linux-gnu")).unwrap();
+        for triple in &["aarch64-unknown-linux-gnu", "i686-unknown-linux-gnu"] {
+            match check_architecture(&test_binary, &target(triple)) {
+                Err(AuditWheelError::ArchitectureMismatchError(found, _)) => {
+                    assert_eq!(found, "X86_64 (64-bit)")
+                }
+                other => panic!("Expected an ArchitectureMismatchError, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_resolve_origin() {
+        assert_eq!(
+            resolve_origin("foo", "$ORIGIN/../foo.libs"),
+            Some("foo.libs".to_string())
+        );
+        assert_eq!(
+            resolve_origin("foo/bar", "${ORIGIN}/./baz"),
+            Some("foo/bar/baz".to_string())
+        );
+        assert_eq!(resolve_origin("", "$ORIGIN"), Some("".to_string()));
+        assert_eq!(resolve_origin("foo", "$ORIGIN/../../lib"), None);
+        assert_eq!(resolve_origin("foo", "/usr/lib"), None);
+        assert_eq!(resolve_origin("foo", "$ORIGINAL"), None);
+    }
+
+    /// A library in the wheel only satisfies a dependency if it's in a directory the RUNPATH
+    /// points to. Skipped if there's no C compiler.
+    #[test]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+    fn test_auditwheel_files_runpath() {
+        use std::process::Command;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+        let cc = |args: &[&str]| {
+            Command::new("cc")
+                .args(args)
+                .current_dir(dir)
+                .status()
+                .map(|status| status.success())
+                .unwrap_or(false)
+        };
+        fs::write(dir.join("dep.c"), "int dep(void) { return 42; }\n").unwrap();
+        fs::write(
+            dir.join("module.c"),
+            "int dep(void);\nint module(void) { return dep(); }\n",
+        )
+        .unwrap();
+        if !cc(&["-shared", "-fPIC", "-o", "libdep.so", "dep.c"]) {
+            return;
+        }
+        assert!(cc(&[
+            "-shared",
+            "-fPIC",
+            "-o",
+            "libmodule.so",
+            "module.c",
+            "-L.",
+            "-ldep",
+            "-Wl,-rpath,$ORIGIN/../module.libs",
+        ]));
+
+        let module = dir.join("libmodule.so");
+        let dep = dir.join("libdep.so");
+        let target = target("x86_64-unknown-linux-gnu");
+        let vendored = vec![
+            ("module/libmodule.so", module.as_path()),
+            ("module.libs/libdep.so", dep.as_path()),
+        ];
+        auditwheel_files(&vendored, &target, &Manylinux::Manylinux2014).unwrap();
+
+        let elsewhere = vec![
+            ("module/libmodule.so", module.as_path()),
+            ("other/libdep.so", dep.as_path()),
+        ];
+        match auditwheel_files(&elsewhere, &target, &Manylinux::Manylinux2014) {
+            Err(AuditWheelError::WheelFileError(path, err)) => {
+                assert_eq!(path, "module/libmodule.so");
+                match *err {
+                    AuditWheelError::ManylinuxValidationError(libraries) => {
+                        assert_eq!(libraries, vec!["libdep.so"])
+                    }
+                    other => panic!("Expected a ManylinuxValidationError, got {:?}", other),
+                }
+            }
+            other => panic!("Expected a WheelFileError, got {:?}", other),
+        }
+    }
+
+    /// memcpy got a new version in glibc 2.14 on x86_64, which manylinux2010 doesn't allow
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+    #[test]
+    fn test_check_symbol_versions() {
+        use std::process::Command;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+        fs::write(
+            dir.join("copy.c"),
+            "#include <string.h>\n\
+             __asm__(\".symver memcpy, memcpy@GLIBC_2.14\");\n\
+             void *copy(void *dest, const void *src, size_t n) { return memcpy(dest, src, n); }\n",
+        )
+        .unwrap();
+        let compiled = Command::new("cc")
+            .args(&[
+                "-shared",
+                "-fPIC",
+                "-fno-builtin",
+                "-o",
+                "libcopy.so",
+                "copy.c",
+            ])
+            .current_dir(dir)
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false);
+        if !compiled {
+            return;
+        }
+
+        let library = dir.join("libcopy.so");
+        let target = target("x86_64-unknown-linux-gnu");
+        match check_symbol_versions(&library, &target, &Manylinux::Manylinux2010) {
+            Err(AuditWheelError::VersionedSymbolTooNewError(symbols)) => {
+                assert_eq!(symbols, vec!["memcpy@GLIBC_2.14"])
+            }
+            other => panic!("Expected a VersionedSymbolTooNewError, got {:?}", other),
+        }
+        check_symbol_versions(&library, &target, &Manylinux::Manylinux(2, 17)).unwrap();
+    }
+
+    #[test]
+    fn test_symbol_version_limits() {
+        let manylinux1 = max_versions("manylinux_2_5");
+        let manylinux2010 = max_versions("manylinux_2_12");
+        assert!(!is_symbol_version_too_new("GLIBC_2.5", &manylinux1));
+        assert!(!is_symbol_version_too_new("GLIBC_2.3.4", &manylinux1));
+        assert!(is_symbol_version_too_new("GLIBC_2.14", &manylinux2010));
+        assert!(is_symbol_version_too_new("GLIBCXX_3.4.9", &manylinux1));
+        assert!(is_symbol_version_too_new("GLIBC_PRIVATE", &manylinux2010));
+        assert!(!is_symbol_version_too_new("OPENSSL_1_1_0", &manylinux1));
+        assert!(!is_symbol_version_too_new("CXXABI_TM_1", &manylinux1));
+        assert!(is_symbol_version_too_new("CXXABI_1.3.2", &manylinux1));
     }
 }
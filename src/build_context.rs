@@ -1,20 +1,33 @@
 #[cfg(feature = "auditwheel")]
-use crate::auditwheel::auditwheel_rs;
+use crate::auditwheel::{
+    auditwheel_files, auditwheel_rs, check_symbol_versions, find_oldest_policy, AuditWheelError,
+};
+use crate::cargo_toml::FeatureVariant;
 use crate::compile;
-use crate::compile::warn_missing_py_init;
+use crate::compile::{audit_exports, check_libpython_link, warn_missing_py_init};
+use crate::debuginfo::{self, debuginfo_archive_path, write_debuginfo_archive, SplitDebugInfo};
+use crate::fingerprint::{is_up_to_date, store_fingerprint, Fingerprint};
+use crate::module_writer::cffi_header_symbols;
+use crate::module_writer::source_date_epoch;
 use crate::module_writer::write_python_part;
-use crate::module_writer::WheelWriter;
+use crate::module_writer::{wheel_path, WheelWriter};
 use crate::module_writer::{write_bin, write_bindings_module, write_cffi_module};
-use crate::source_distribution::{get_pyproject_toml, source_distribution, warn_on_local_deps};
+#[cfg(feature = "auditwheel")]
+use crate::repair::repair_artifact;
+use crate::source_distribution::{get_pyproject_toml, source_distribution};
 use crate::Manylinux;
 use crate::Metadata21;
 use crate::PythonInterpreter;
 use crate::Target;
 use anyhow::{anyhow, bail, Context, Result};
 use cargo_metadata::Metadata;
-use std::collections::HashMap;
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use tempfile::tempdir;
 
 /// The way the rust code is used in the wheel
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -26,6 +39,9 @@ pub enum BridgeModel {
     /// A native module with pyo3 or rust-cpython bindings. The String is the name of the bindings
     /// providing crate, e.g. pyo3.
     Bindings(String),
+    /// A native module with pyo3 bindings against the stable ABI (abi3), which is compatible with
+    /// all CPython versions starting with the contained major and minor version
+    BindingsAbi3(u8, u8),
 }
 
 impl BridgeModel {
@@ -33,6 +49,7 @@ impl BridgeModel {
     pub fn unwrap_bindings(&self) -> &str {
         match self {
             BridgeModel::Bindings(value) => &value,
+            BridgeModel::BindingsAbi3(..) => "pyo3",
             _ => panic!("Expected Bindings"),
         }
     }
@@ -67,7 +84,26 @@ impl ProjectLayout {
     }
 }
 
+/// Normalizes the name of a variant into a PEP 440 local version label, which may only contain
+/// lowercase ascii letters, digits and dots
+fn local_version_label(name: &str) -> Result<String> {
+    let re = Regex::new(r"[^a-z0-9]+").unwrap();
+    let label = re
+        .replace_all(&name.to_ascii_lowercase(), ".")
+        .trim_matches('.')
+        .to_string();
+    if label.is_empty() {
+        bail!(
+            "The variant name {:?} can't be used as local version label. \
+             Please give the variant a distribution name instead",
+            name
+        );
+    }
+    Ok(label)
+}
+
 /// Contains all the metadata required to build the crate
+#[derive(Clone)]
 pub struct BuildContext {
     /// The platform, i.e. os and pointer width
     pub target: Target,
@@ -83,6 +119,8 @@ pub struct BuildContext {
     /// because package names normally contain minuses while module names
     /// have underscores. The package name is part of metadata21
     pub module_name: String,
+    /// The name of the cargo package, which is used to find the artifacts in cargo's output
+    pub crate_name: String,
     /// The path to the Cargo.toml. Required for the cargo invocations
     pub manifest_path: PathBuf,
     /// The directory to store the built wheels in. Defaults to a new "wheels"
@@ -90,11 +128,31 @@ pub struct BuildContext {
     pub out: PathBuf,
     /// Pass --release to cargo
     pub release: bool,
+    /// Build with this cargo profile instead of the release or dev profile
+    pub profile: Option<String>,
+    /// The binaries to build and package for bin bindings
+    pub bins: Vec<String>,
     /// Strip the library for minimum file size
     pub strip: bool,
+    /// Save the debug info of the native libraries and binaries to a `.dbg.zip` next to each
+    /// wheel, keyed by their GNU build-id, and package them stripped
+    pub split_debuginfo: bool,
     /// Whether to use the the manylinux and check compliance (on), use it but don't
-    /// check compliance (no-auditwheel) or use the native linux tag (off)
+    /// check compliance (no-auditwheel), pick the oldest compliant policy (auto) or use the
+    /// native linux tag (off)
     pub manylinux: Manylinux,
+    /// Vendor the external shared libraries that violate the manylinux policy into the wheel
+    /// instead of failing the build
+    pub repair: bool,
+    /// Only warn instead of failing when the native library links libpython
+    pub allow_libpython_link: bool,
+    /// The number of wheels to build at the same time when building for multiple interpreters
+    pub jobs: usize,
+    /// List the symbols the native library exports beyond the expected entry points
+    pub audit_exports: bool,
+    /// Fail when the native library exports more unexpected symbols than this. Implies
+    /// `audit_exports`
+    pub max_unexpected_exports: Option<usize>,
     /// Extra arguments that will be passed to cargo as `cargo rustc [...] [arg1] [arg2] --`
     pub cargo_extra_args: Vec<String>,
     /// Extra arguments that will be passed to rustc as `cargo rustc [...] -- [arg1] [arg2]`
@@ -103,6 +161,9 @@ pub struct BuildContext {
     pub interpreter: Vec<PythonInterpreter>,
     /// Cargo.toml as resolved by [cargo_metadata]
     pub cargo_metadata: Metadata,
+    /// The feature variants from Cargo.toml, each of which gets its own wheels. Empty if there
+    /// are none or if a single variant was selected, which is then already applied
+    pub variants: Vec<(String, FeatureVariant)>,
 }
 
 type BuiltWheelMetadata = (PathBuf, String, Option<PythonInterpreter>);
@@ -115,28 +176,81 @@ impl BuildContext {
         fs::create_dir_all(&self.out)
             .context("Failed to create the target directory for the wheels")?;
 
+        if !self.variants.is_empty() {
+            return self.build_variant_wheels();
+        }
+
         let wheels = match &self.bridge {
             BridgeModel::Cffi => vec![(self.build_cffi_wheel()?, "py3".to_string(), None)],
             BridgeModel::Bin => vec![(self.build_bin_wheel()?, "py3".to_string(), None)],
             BridgeModel::Bindings(_) => self.build_binding_wheels()?,
+            BridgeModel::BindingsAbi3(major, minor) => vec![self.build_abi3_wheel(*major, *minor)?],
         };
 
         Ok(wheels)
     }
 
+    /// Builds the wheels of every feature variant, one after the other
+    fn build_variant_wheels(&self) -> Result<Vec<BuiltWheelMetadata>> {
+        let mut wheels = Vec::new();
+        let mut report = Vec::new();
+        for (name, variant) in &self.variants {
+            println!("🧩 Building the {} variant", name);
+            let mut context = self.clone();
+            context.variants = Vec::new();
+            context.apply_variant(name, variant)?;
+            let variant_wheels = context.build_wheels()?;
+            report.push((name, variant_wheels.len(), context.metadata21));
+            wheels.extend(variant_wheels);
+        }
+
+        println!("🧩 Built {} variants:", report.len());
+        for (name, count, metadata21) in report {
+            println!(
+                "  {}: {} {} with {} wheel(s)",
+                name, metadata21.name, metadata21.version, count
+            );
+        }
+
+        Ok(wheels)
+    }
+
+    /// Activates the features of the variant and renames the distribution or adds the PEP 440
+    /// local version label, so that the wheels of the variants don't overwrite each other
+    pub fn apply_variant(&mut self, name: &str, variant: &FeatureVariant) -> Result<()> {
+        self.cargo_extra_args.extend(variant.cargo_args());
+        match variant.name {
+            Some(ref distribution) => self.metadata21.name = distribution.clone(),
+            None => {
+                let label = local_version_label(name)?;
+                let separator = if self.metadata21.version.contains('+') {
+                    "."
+                } else {
+                    "+"
+                };
+                self.metadata21.version =
+                    format!("{}{}{}", self.metadata21.version, separator, label);
+            }
+        }
+        Ok(())
+    }
+
     /// Builds a source distribution and returns the same metadata as [BuildContext::build_wheels]
-    pub fn build_source_distribution(&self) -> Result<Option<BuiltWheelMetadata>> {
+    ///
+    /// With `vendor`, the dependencies are vendored into the source distribution
+    pub fn build_source_distribution(&self, vendor: bool) -> Result<Option<BuiltWheelMetadata>> {
         fs::create_dir_all(&self.out)
             .context("Failed to create the target directory for the source distribution")?;
 
         match get_pyproject_toml(self.manifest_path.parent().unwrap()) {
             Ok(pyproject) => {
-                warn_on_local_deps(&self.cargo_metadata);
                 let sdist_path = source_distribution(
                     &self.out,
                     &self.metadata21,
                     &self.manifest_path,
                     pyproject.sdist_include(),
+                    pyproject.sdist_exclude(),
+                    vendor,
                 )
                 .context("Failed to build source distribution")?;
                 Ok(Some((sdist_path, "source".to_string(), None)))
@@ -151,58 +265,253 @@ impl BuildContext {
     /// Defaults to 3.{5, 6, 7, 8, 9} if no python versions are given
     /// and silently ignores all non-existent python versions.
     ///
+    /// With more than one job, up to that many wheels are built at the same time. The wheels are
+    /// always returned in the order of the interpreters.
+    ///
     /// Runs [auditwheel_rs()] if not deactivated
     pub fn build_binding_wheels(
         &self,
     ) -> Result<Vec<(PathBuf, String, Option<PythonInterpreter>)>> {
         let mut wheels = Vec::new();
-        for python_interpreter in &self.interpreter {
-            let artifact =
-                self.compile_cdylib(Some(&python_interpreter), Some(&self.module_name))?;
-
-            let tag = python_interpreter.get_tag(&self.manylinux);
-
-            let mut writer = WheelWriter::new(
-                &tag,
-                &self.out,
-                &self.metadata21,
-                &self.scripts,
-                &[tag.clone()],
-            )?;
-
-            write_bindings_module(
-                &mut writer,
-                &self.project_layout,
-                &self.module_name,
-                &artifact,
-                python_interpreter,
-                false,
-            )
-            .context("Failed to add the files to the wheel")?;
-
-            let wheel_path = writer.finish()?;
+        if self.jobs > 1 && self.interpreter.len() > 1 {
+            for result in self.build_binding_wheels_parallel()? {
+                wheels.push(result?);
+            }
+            println!("📦 Built {} wheels:", wheels.len());
+            for (wheel_path, _, _) in &wheels {
+                println!("   - {}", wheel_path.display());
+            }
+        } else {
+            for python_interpreter in &self.interpreter {
+                wheels.push(self.build_binding_wheel(python_interpreter)?);
+            }
+        }
 
-            println!(
-                "📦 Built wheel for {} {}.{}{} to {}",
-                python_interpreter.interpreter,
-                python_interpreter.major,
-                python_interpreter.minor,
-                python_interpreter.abiflags,
-                wheel_path.display()
-            );
+        Ok(wheels)
+    }
+
+    /// Runs [BuildContext::build_binding_wheel] for all interpreters on `jobs` threads and
+    /// returns the results in the order of the interpreters
+    fn build_binding_wheels_parallel(&self) -> Result<Vec<Result<BuiltWheelMetadata>>> {
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<Option<Result<BuiltWheelMetadata>>>> =
+            Mutex::new(self.interpreter.iter().map(|_| None).collect());
+
+        crossbeam_utils::thread::scope(|scope| {
+            for _ in 0..self.jobs.min(self.interpreter.len()) {
+                scope.spawn(|_| loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let python_interpreter = match self.interpreter.get(index) {
+                        Some(python_interpreter) => python_interpreter,
+                        None => break,
+                    };
+                    let result = self.build_binding_wheel(python_interpreter);
+                    results.lock().unwrap()[index] = Some(result);
+                });
+            }
+        })
+        .map_err(|_| anyhow!("A thread building a wheel panicked"))?;
+
+        Ok(results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|result| result.expect("Every interpreter should have been built"))
+            .collect())
+    }
 
-            wheels.push((
-                wheel_path,
-                format!("cp{}{}", python_interpreter.major, python_interpreter.minor),
-                Some(python_interpreter.clone()),
-            ));
+    /// Compiles the crate for one python interpreter and packages it into a wheel
+    fn build_binding_wheel(
+        &self,
+        python_interpreter: &PythonInterpreter,
+    ) -> Result<BuiltWheelMetadata> {
+        let (artifact, manylinux) =
+            self.compile_cdylib(Some(python_interpreter), Some(&self.module_name))?;
+
+        let tag = python_interpreter.get_tag(&manylinux);
+        let python_tag = format!("cp{}{}", python_interpreter.major, python_interpreter.minor);
+        let library_name = python_interpreter.get_library_name(&self.module_name);
+        let fingerprint =
+            self.wheel_fingerprint(&artifact, std::slice::from_ref(&tag), &[&library_name], &[])?;
+        if let Some(wheel_path) = self.up_to_date_wheel(&tag, &fingerprint) {
+            return Ok((wheel_path, python_tag, Some(python_interpreter.clone())));
         }
 
-        Ok(wheels)
+        let wheel_path = self.write_binding_wheel(
+            &artifact,
+            &manylinux,
+            &tag,
+            Some(python_interpreter),
+            &python_interpreter.target,
+        )?;
+        if let Some(fingerprint) = fingerprint {
+            store_fingerprint(&wheel_path, &fingerprint)?;
+        }
+
+        println!(
+            "📦 Built wheel for {} {}.{}{} to {}",
+            python_interpreter.interpreter,
+            python_interpreter.major,
+            python_interpreter.minor,
+            python_interpreter.abiflags,
+            wheel_path.display()
+        );
+
+        Ok((wheel_path, python_tag, Some(python_interpreter.clone())))
+    }
+
+    /// Builds a single wheel against the stable ABI that works with all CPython versions
+    /// starting with `major.minor`, compiling with the first interpreter. Return type is the same
+    /// as [BuildContext::build_wheels()]
+    pub fn build_abi3_wheel(&self, major: u8, minor: u8) -> Result<BuiltWheelMetadata> {
+        let python_interpreter = self
+            .interpreter
+            .first()
+            .ok_or_else(|| anyhow!("Building an abi3 wheel requires a python interpreter"))?;
+        let (artifact, manylinux) =
+            self.compile_cdylib(Some(python_interpreter), Some(&self.module_name))?;
+
+        let platform = self.target.get_platform_tag(&manylinux);
+        let tag = format!("cp{}{}-abi3-{}", major, minor, platform);
+        let python_tag = format!("cp{}{}", major, minor);
+        let fingerprint =
+            self.wheel_fingerprint(&artifact, std::slice::from_ref(&tag), &["abi3"], &[])?;
+        if let Some(wheel_path) = self.up_to_date_wheel(&tag, &fingerprint) {
+            return Ok((wheel_path, python_tag, Some(python_interpreter.clone())));
+        }
+
+        let wheel_path =
+            self.write_binding_wheel(&artifact, &manylinux, &tag, None, &self.target)?;
+        if let Some(fingerprint) = fingerprint {
+            store_fingerprint(&wheel_path, &fingerprint)?;
+        }
+
+        println!(
+            "📦 Built abi3 wheel for CPython {}.{}+ to {}",
+            major,
+            minor,
+            wheel_path.display()
+        );
+
+        Ok((wheel_path, python_tag, Some(python_interpreter.clone())))
+    }
+
+    /// Packages the compiled bindings module into a wheel with the given tag. Without a python
+    /// interpreter, the module gets the abi3 file name
+    fn write_binding_wheel(
+        &self,
+        artifact: &Path,
+        manylinux: &Manylinux,
+        tag: &str,
+        python_interpreter: Option<&PythonInterpreter>,
+        target: &Target,
+    ) -> Result<PathBuf> {
+        let mut writer = WheelWriter::new(
+            tag,
+            &self.out,
+            &self.metadata21,
+            &self.scripts,
+            &[tag.to_string()],
+        )?;
+
+        let artifact_dir = match self.project_layout {
+            ProjectLayout::Mixed(_) => PathBuf::from(&self.module_name),
+            ProjectLayout::PureRust => PathBuf::new(),
+        };
+        let tempdir = tempdir()?;
+        let artifact = self.repair(
+            &mut writer,
+            artifact,
+            &artifact_dir,
+            manylinux,
+            tempdir.path(),
+        )?;
+        let (artifact, debuginfo) = self.strip_artifact(&artifact, tempdir.path())?;
+
+        write_bindings_module(
+            &mut writer,
+            &self.project_layout,
+            &self.module_name,
+            &artifact,
+            python_interpreter,
+            target,
+            false,
+        )
+        .context("Failed to add the files to the wheel")?;
+
+        self.auditwheel_contents(&writer, target, manylinux)?;
+        let wheel_path = writer.finish()?;
+        let debuginfo: Vec<_> = debuginfo
+            .into_iter()
+            .map(|split| (self.module_name.clone(), split))
+            .collect();
+        self.write_debuginfo(&wheel_path, &debuginfo)?;
+        Ok(wheel_path)
+    }
+
+    /// Hashes everything that goes into a wheel: The compiled artifact, the metadata, the scripts,
+    /// the python part of mixed projects and the tags, plus the given extra values and files.
+    /// The settings and environment the wheel was built with are included too, e.g. the
+    /// features of the variant and `SOURCE_DATE_EPOCH`, even if they don't change the artifact.
+    ///
+    /// Returns `None` when repairing, since the vendored libraries come from the build host and
+    /// can change at any time
+    fn wheel_fingerprint(
+        &self,
+        artifact: &Path,
+        tags: &[String],
+        extra: &[&str],
+        extra_files: &[PathBuf],
+    ) -> Result<Option<String>> {
+        if self.repair {
+            return Ok(None);
+        }
+
+        let mut fingerprint = Fingerprint::new();
+        fingerprint.add_file(artifact)?;
+        fingerprint.add_serialized(&self.metadata21)?;
+        let scripts: BTreeMap<&String, &String> = self.scripts.iter().collect();
+        fingerprint.add_serialized(&scripts)?;
+        fingerprint.add_str(&self.module_name);
+        fingerprint.add_serialized(&self.split_debuginfo)?;
+        fingerprint.add_str(&source_date_epoch()?.to_string());
+        fingerprint.add_serialized(&self.cargo_extra_args)?;
+        fingerprint.add_serialized(&self.rustc_extra_args)?;
+        fingerprint.add_serialized(&(self.release, &self.profile, self.strip))?;
+        fingerprint.add_str(&self.manylinux.to_string());
+        fingerprint.add_str(&format!("{:?}", self.bridge));
+        if let ProjectLayout::Mixed(python_module) = &self.project_layout {
+            fingerprint.add_dir(python_module)?;
+        }
+        for tag in tags {
+            fingerprint.add_str(tag);
+        }
+        for value in extra {
+            fingerprint.add_str(value);
+        }
+        for file in extra_files {
+            fingerprint.add_optional_file(file)?;
+        }
+        Ok(Some(fingerprint.finish()))
+    }
+
+    /// Returns the path to the wheel with the given tag if it was already built from inputs with
+    /// the same fingerprint
+    fn up_to_date_wheel(&self, tag: &str, fingerprint: &Option<String>) -> Option<PathBuf> {
+        let fingerprint = fingerprint.as_ref()?;
+        let wheel_path = wheel_path(&self.out, &self.metadata21, tag);
+        let debuginfo_missing =
+            self.split_debuginfo && !debuginfo_archive_path(&wheel_path).is_file();
+        if is_up_to_date(&wheel_path, fingerprint) && !debuginfo_missing {
+            println!("📦 {} is up to date", wheel_path.display());
+            Some(wheel_path)
+        } else {
+            None
+        }
     }
 
     /// Runs cargo build, extracts the cdylib from the output, runs auditwheel and returns the
-    /// artifact
+    /// artifact together with the manylinux policy to use for the platform tag
     ///
     /// The module name is used to warn about missing a `PyInit_<module name>` function for
     /// bindings modules.
@@ -210,43 +519,206 @@ impl BuildContext {
         &self,
         python_interpreter: Option<&PythonInterpreter>,
         module_name: Option<&str>,
-    ) -> Result<PathBuf> {
+    ) -> Result<(PathBuf, Manylinux)> {
         let artifacts = compile(&self, python_interpreter, &self.bridge)
             .context("Failed to build a native library through cargo")?;
 
-        let artifact = artifacts.get("cdylib").cloned().ok_or_else(|| {
-            anyhow!(
-                "Cargo didn't build a cdylib. Did you miss crate-type = [\"cdylib\"] \
+        let artifact = artifacts
+            .iter()
+            .find_map(|artifact| artifact.get("cdylib"))
+            .cloned()
+            .ok_or_else(|| {
+                anyhow!(
+                    "Cargo didn't build a cdylib. Did you miss crate-type = [\"cdylib\"] \
                  in the lib section of your Cargo.toml?",
-            )
-        })?;
+                )
+            })?;
+        let target = python_interpreter
+            .map(|x| &x.target)
+            .unwrap_or(&self.target);
+        let manylinux = self.auditwheel(&artifact, target)?;
+
+        check_libpython_link(&artifact, self.allow_libpython_link)?;
+
+        if let Some(module_name) = module_name {
+            warn_missing_py_init(&artifact, module_name)
+                .context("Failed to parse the native library")?;
+        }
+
+        if self.audit_exports || self.max_unexpected_exports.is_some() {
+            let expected = match self.bridge {
+                BridgeModel::Cffi => cffi_header_symbols(self.manifest_path.parent().unwrap())?,
+                _ => vec![format!("PyInit_{}", self.module_name)],
+            };
+            audit_exports(&artifact, &expected, self.max_unexpected_exports)
+                .context("Failed to audit the exported symbols")?;
+        }
+
+        Ok((artifact, manylinux))
+    }
+
+    /// Checks the artifact for manylinux compliance and returns the manylinux policy to use for
+    /// the platform tag, which for [Manylinux::Auto] is the oldest policy the artifact
+    /// complies with.
+    ///
+    /// When repairing, the external libraries are allowed since they are vendored into the wheel
+    /// by [BuildContext::repair]
+    #[cfg_attr(not(feature = "auditwheel"), allow(unused_variables))]
+    fn auditwheel(&self, artifact: &Path, target: &Target) -> Result<Manylinux> {
         #[cfg(feature = "auditwheel")]
         {
-            let target = python_interpreter
-                .map(|x| &x.target)
-                .unwrap_or(&self.target);
+            if self.manylinux == Manylinux::Auto {
+                let manylinux = find_oldest_policy(artifact, target, self.repair)
+                    .context("Failed to find a manylinux policy the library complies with")?;
+                if target.is_linux() {
+                    println!("🔍 The library complies with {}", manylinux);
+                }
+                return Ok(manylinux);
+            }
+
+            match auditwheel_rs(artifact, target, &self.manylinux) {
+                Err(AuditWheelError::ManylinuxValidationError(_)) if self.repair => {
+                    check_symbol_versions(artifact, target, &self.manylinux)
+                }
+                result => result,
+            }
+            .context("Failed to ensure manylinux compliance")?;
+        }
+
+        #[cfg(not(feature = "auditwheel"))]
+        {
+            if self.manylinux == Manylinux::Auto && target.is_linux() {
+                bail!("--manylinux=auto requires maturin to be built with the auditwheel feature");
+            }
+        }
 
-            auditwheel_rs(&artifact, target, &self.manylinux)
+        Ok(self.manylinux.clone())
+    }
+
+    /// Checks every elf file that was added to the wheel against the manylinux policy of its
+    /// platform tag, which catches shared libraries shipped in the python part of mixed projects.
+    /// Libraries that are shipped in the wheel themselves may be linked.
+    #[cfg_attr(not(feature = "auditwheel"), allow(unused_variables))]
+    fn auditwheel_contents(
+        &self,
+        writer: &WheelWriter,
+        target: &Target,
+        manylinux: &Manylinux,
+    ) -> Result<()> {
+        #[cfg(feature = "auditwheel")]
+        {
+            auditwheel_files(&writer.elf_files(), target, manylinux)
                 .context("Failed to ensure manylinux compliance")?;
         }
+        Ok(())
+    }
 
-        if let Some(module_name) = module_name {
-            warn_missing_py_init(&artifact, module_name)
-                .context("Failed to parse the native library")?;
+    /// If repairing is enabled, copies the external shared libraries the artifact links into the
+    /// wheel and returns the path to a copy of the artifact that was patched to load them.
+    /// Otherwise the artifact is returned unchanged.
+    ///
+    /// `artifact_dir` is the directory the artifact will be placed in inside the wheel
+    #[cfg_attr(not(feature = "auditwheel"), allow(unused_variables))]
+    fn repair(
+        &self,
+        writer: &mut WheelWriter,
+        artifact: &Path,
+        artifact_dir: &Path,
+        manylinux: &Manylinux,
+        tempdir: &Path,
+    ) -> Result<PathBuf> {
+        #[cfg(feature = "auditwheel")]
+        {
+            if self.repair && self.target.is_linux() {
+                return repair_artifact(
+                    writer,
+                    artifact,
+                    artifact_dir,
+                    &self.module_name,
+                    &self.target,
+                    manylinux,
+                    tempdir,
+                )
+                .context("Failed to repair the wheel");
+            }
         }
+        Ok(artifact.to_path_buf())
+    }
 
-        Ok(artifact)
+    /// With `--split-debuginfo`, splits the debug info off the artifact and returns the stripped
+    /// copy that goes into the wheel together with the debug info
+    fn strip_artifact(
+        &self,
+        artifact: &Path,
+        tempdir: &Path,
+    ) -> Result<(PathBuf, Option<SplitDebugInfo>)> {
+        if !self.split_debuginfo {
+            return Ok((artifact.to_path_buf(), None));
+        }
+        let split = debuginfo::split_debuginfo(artifact, tempdir).context(format!(
+            "Failed to split the debug info off {}",
+            artifact.display()
+        ))?;
+        Ok((split.stripped.clone(), Some(split)))
+    }
+
+    /// Saves the debug info that was split off the files in the wheel next to it
+    fn write_debuginfo(
+        &self,
+        wheel_path: &Path,
+        debuginfo: &[(String, SplitDebugInfo)],
+    ) -> Result<()> {
+        if debuginfo.is_empty() {
+            return Ok(());
+        }
+        let archive_path = write_debuginfo_archive(wheel_path, debuginfo)?;
+        for (name, split) in debuginfo {
+            println!("🐛 {} has the build-id {}", name, split.build_id);
+        }
+        println!("🐛 Saved the debug info to {}", archive_path.display());
+        Ok(())
     }
 
     /// Builds a wheel with cffi bindings
     pub fn build_cffi_wheel(&self) -> Result<PathBuf> {
-        let artifact = self.compile_cdylib(None, None)?;
+        let (artifact, manylinux) = self.compile_cdylib(None, None)?;
 
-        let (tag, tags) = self.target.get_universal_tags(&self.manylinux);
+        let (tag, tags) = self.target.get_universal_tags(&manylinux);
+
+        // The cffi declarations are generated from the header, which is either given or
+        // generated by cbindgen with the configuration from cbindgen.toml
+        let crate_dir = self.manifest_path.parent().unwrap();
+        let python = self.interpreter[0].executable.to_string_lossy();
+        let fingerprint = self.wheel_fingerprint(
+            &artifact,
+            &tags,
+            &[&python],
+            &[
+                crate_dir.join("target").join("header.h"),
+                crate_dir.join("cbindgen.toml"),
+            ],
+        )?;
+        if let Some(wheel_path) = self.up_to_date_wheel(&tag, &fingerprint) {
+            return Ok(wheel_path);
+        }
 
         let mut builder =
             WheelWriter::new(&tag, &self.out, &self.metadata21, &self.scripts, &tags)?;
 
+        let artifact_dir = match self.project_layout {
+            ProjectLayout::Mixed(_) => Path::new(&self.module_name).join(&self.module_name),
+            ProjectLayout::PureRust => PathBuf::from(&self.module_name),
+        };
+        let tempdir = tempdir()?;
+        let artifact = self.repair(
+            &mut builder,
+            &artifact,
+            &artifact_dir,
+            &manylinux,
+            tempdir.path(),
+        )?;
+        let (artifact, debuginfo) = self.strip_artifact(&artifact, tempdir.path())?;
+
         write_cffi_module(
             &mut builder,
             &self.project_layout,
@@ -257,7 +729,16 @@ impl BuildContext {
             false,
         )?;
 
+        self.auditwheel_contents(&builder, &self.target, &manylinux)?;
         let wheel_path = builder.finish()?;
+        let debuginfo: Vec<_> = debuginfo
+            .into_iter()
+            .map(|split| (self.module_name.clone(), split))
+            .collect();
+        self.write_debuginfo(&wheel_path, &debuginfo)?;
+        if let Some(fingerprint) = fingerprint {
+            store_fingerprint(&wheel_path, &fingerprint)?;
+        }
 
         println!("📦 Built wheel to {}", wheel_path.display());
 
@@ -269,23 +750,51 @@ impl BuildContext {
     /// Runs [auditwheel_rs()] if not deactivated
     pub fn build_bin_wheel(&self) -> Result<PathBuf> {
         let artifacts = compile(&self, None, &self.bridge)
-            .context("Failed to build a native library through cargo")?;
-
-        let artifact = artifacts
-            .get("bin")
-            .cloned()
-            .ok_or_else(|| anyhow!("Cargo didn't build a binary"))?;
+            .context("Failed to build a native library through cargo")?
+            .into_iter()
+            .map(|artifact| {
+                artifact
+                    .get("bin")
+                    .cloned()
+                    .ok_or_else(|| anyhow!("Cargo didn't build a binary"))
+            })
+            .collect::<Result<Vec<PathBuf>>>()?;
+        if artifacts.is_empty() {
+            bail!("Cargo didn't build a binary");
+        }
 
-        #[cfg(feature = "auditwheel")]
-        auditwheel_rs(&artifact, &self.target, &self.manylinux)
-            .context("Failed to ensure manylinux compliance")?;
+        // The wheel can only have one platform tag, so we need the newest policy of all binaries
+        let manylinux = artifacts
+            .iter()
+            .map(|artifact| self.auditwheel(artifact, &self.target))
+            .collect::<Result<Vec<Manylinux>>>()?
+            .into_iter()
+            .max_by_key(|policy| (policy.glibc_version(), policy.musl_version()))
+            .unwrap();
 
-        let (tag, tags) = self.target.get_universal_tags(&self.manylinux);
+        let (tag, tags) = self.target.get_universal_tags(&manylinux);
 
         if !self.scripts.is_empty() {
             bail!("Defining entrypoints and working with a binary doesn't mix well");
         }
 
+        let bin_names: Vec<String> = artifacts
+            .iter()
+            .map(|artifact| {
+                artifact
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        let bin_names: Vec<&str> = bin_names.iter().map(String::as_str).collect();
+        let fingerprint =
+            self.wheel_fingerprint(&artifacts[0], &tags, &bin_names, &artifacts[1..])?;
+        if let Some(wheel_path) = self.up_to_date_wheel(&tag, &fingerprint) {
+            return Ok(wheel_path);
+        }
+
         let mut builder =
             WheelWriter::new(&tag, &self.out, &self.metadata21, &self.scripts, &tags)?;
 
@@ -297,17 +806,45 @@ impl BuildContext {
             ProjectLayout::PureRust => {}
         }
 
-        // I wouldn't know of any case where this would be the wrong (and neither do
-        // I know a better alternative)
-        let bin_name = artifact
-            .file_name()
-            .expect("Couldn't get the filename from the binary produced by cargo");
-        write_bin(&mut builder, &artifact, &self.metadata21, bin_name)?;
+        let tempdir = tempdir()?;
+        let mut debuginfo = Vec::new();
+        for artifact in &artifacts {
+            // I wouldn't know of any case where this would be the wrong (and neither do
+            // I know a better alternative)
+            let bin_name = artifact
+                .file_name()
+                .expect("Couldn't get the filename from the binary produced by cargo");
+            let (stripped, split) = self.strip_artifact(artifact, tempdir.path())?;
+            write_bin(&mut builder, &stripped, &self.metadata21, bin_name)?;
+            if let Some(split) = split {
+                debuginfo.push((bin_name.to_string_lossy().to_string(), split));
+            }
+        }
 
+        self.auditwheel_contents(&builder, &self.target, &manylinux)?;
         let wheel_path = builder.finish()?;
+        self.write_debuginfo(&wheel_path, &debuginfo)?;
+        if let Some(fingerprint) = fingerprint {
+            store_fingerprint(&wheel_path, &fingerprint)?;
+        }
 
         println!("📦 Built wheel to {}", wheel_path.display());
 
         Ok(wheel_path)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_local_version_label() {
+        assert_eq!(local_version_label("fast").unwrap(), "fast");
+        assert_eq!(
+            local_version_label("AVX2_Fast-path").unwrap(),
+            "avx2.fast.path"
+        );
+        assert!(local_version_label("-_-").is_err());
+    }
+}
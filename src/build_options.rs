@@ -1,4 +1,5 @@
 use crate::build_context::{BridgeModel, ProjectLayout};
+use crate::python_interpreter::Interpreter;
 use crate::BuildContext;
 use crate::CargoToml;
 use crate::Manylinux;
@@ -6,9 +7,9 @@ use crate::Metadata21;
 use crate::PythonInterpreter;
 use crate::Target;
 use anyhow::{bail, format_err, Context, Result};
-use cargo_metadata::{Metadata, MetadataCommand, Node};
+use cargo_metadata::{Metadata, MetadataCommand, Node, PackageId};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use structopt::StructOpt;
 
@@ -23,22 +24,33 @@ pub struct BuildOptions {
     /// - `1-unchecked`: Use the manylinux1 tag without checking for compliance{n}
     /// - `2010`: Use the manylinux2010 tag and check for compliance{n}
     /// - `2010-unchecked`: Use the manylinux2010 tag without checking for compliance{n}
-    /// - `2014`: Use the manylinux2010 tag and check for compliance{n}
+    /// - `2014`: Use the manylinux2014 tag and check for compliance{n}
     /// - `2014-unchecked`: Use the manylinux2014 tag without checking for compliance{n}
+    /// - `2_x`: Use the PEP 600 manylinux_2_x tag (e.g. `2_24`) and check for compliance{n}
+    /// - `2_x-unchecked`: Use the PEP 600 manylinux_2_x tag without checking for compliance{n}
+    /// - `musllinux_1_x`: Use the PEP 656 musllinux_1_x tag (for musl targets) and check for compliance{n}
+    /// - `musllinux_1_x-unchecked`: Use the PEP 656 musllinux_1_x tag without checking for compliance{n}
+    /// - `auto`: Use the oldest manylinux tag the compiled library complies with{n}
     /// - `off`: Use the native linux tag (off)
     ///
     /// This option is ignored on all non-linux platforms
-    #[structopt(
-        long,
-        possible_values = &["1", "1-unchecked", "2010", "2010-unchecked", "2014", "2014-unchecked", "off"],
-        case_insensitive = true,
-        default_value = "1"
-    )]
-    pub manylinux: Manylinux,
+    ///
+    /// The tags of manylinux1, manylinux2010 and manylinux2014 wheels also contain the
+    /// equivalent PEP 600 tag, e.g. manylinux_2_17 for manylinux2014. For musl targets, the
+    /// manylinux policies are replaced by musllinux_1_1
+    ///
+    /// Defaults to `1`, or to `2014` for aarch64 and armv7, which have no older manylinux policy
+    #[structopt(long)]
+    pub manylinux: Option<Manylinux>,
     #[structopt(short, long)]
     /// The python versions to build wheels for, given as the names of the
     /// interpreters. Uses autodiscovery if not explicitly set.
     pub interpreter: Option<Vec<PathBuf>>,
+    /// Build for python interpreters that can't be run, e.g. when cross compiling, given as the
+    /// output of `python -m sysconfig` or as their `_sysconfigdata` module. Sets the
+    /// PYO3_CROSS_* environment variables for pyo3
+    #[structopt(long, parse(from_os_str))]
+    pub interpreter_sysconfig: Vec<PathBuf>,
     /// Which kind of bindings to use. Possible values are pyo3, rust-cpython, cffi and bin
     #[structopt(short, long)]
     pub bindings: Option<String>,
@@ -58,6 +70,48 @@ pub struct BuildOptions {
     /// [deprecated, use --manylinux instead] Don't check for manylinux compliance
     #[structopt(long = "skip-auditwheel")]
     pub skip_auditwheel: bool,
+    /// Instead of failing, copy the shared libraries that violate the manylinux policy from the
+    /// build host into the wheel and patch the native module to load them, similar to
+    /// `auditwheel repair`. Requires patchelf. Not supported for binaries
+    #[structopt(long)]
+    pub repair: bool,
+    /// Only warn instead of failing when the native library links libpython, which usually
+    /// means that pyo3's `extension-module` feature isn't activated
+    #[structopt(long)]
+    pub allow_libpython_link: bool,
+    /// List the symbols the native library exports beyond `PyInit_<module name>` (pyo3 and
+    /// rust-cpython) or beyond the functions declared in the header (cffi)
+    #[structopt(long)]
+    pub audit_exports: bool,
+    /// Fail when the native library exports more than this many unexpected symbols. Implies
+    /// --audit-exports
+    #[structopt(long, name = "N")]
+    pub max_unexpected_exports: Option<usize>,
+    /// The number of wheels to build at the same time when building for multiple python
+    /// interpreters. Each interpreter has its own cargo target directory
+    #[structopt(long, default_value = "1")]
+    pub jobs: usize,
+    /// Save the debug info of the native library or binaries to a `.dbg.zip` next to each wheel,
+    /// keyed by their GNU build-id, and package them stripped. Linux only, requires objcopy.
+    /// Builds with full debug info unless the rustc arguments or RUSTFLAGS set a debuginfo level
+    #[structopt(long)]
+    pub split_debuginfo: bool,
+    /// Only build this feature variant from `[package.metadata.maturin.variants]` instead of all
+    /// of them
+    #[structopt(long)]
+    pub variant: Option<String>,
+    /// The package to build in a cargo workspace, which is required if the manifest path points to
+    /// the root of a virtual workspace
+    #[structopt(long)]
+    pub package: Option<String>,
+    /// Build with this cargo profile, e.g. a `dist` profile with lto, instead of the release or
+    /// dev profile
+    #[structopt(long)]
+    pub profile: Option<String>,
+    /// The binary to package for bin bindings. Can be repeated. Defaults to all binaries of the
+    /// package
+    #[structopt(long = "bin", name = "NAME", number_of_values = 1)]
+    pub bins: Vec<String>,
     /// The --target option for cargo
     #[structopt(long, name = "TRIPLE")]
     pub target: Option<String>,
@@ -76,12 +130,23 @@ pub struct BuildOptions {
 impl Default for BuildOptions {
     fn default() -> Self {
         BuildOptions {
-            manylinux: Manylinux::Manylinux1,
+            manylinux: None,
             interpreter: Some(vec![]),
+            interpreter_sysconfig: Vec::new(),
             bindings: None,
             manifest_path: PathBuf::from("Cargo.toml"),
             out: None,
             skip_auditwheel: false,
+            repair: false,
+            allow_libpython_link: false,
+            audit_exports: false,
+            max_unexpected_exports: None,
+            jobs: 1,
+            split_debuginfo: false,
+            variant: None,
+            package: None,
+            profile: None,
+            bins: Vec::new(),
             target: None,
             cargo_extra_args: Vec::new(),
             rustc_extra_args: Vec::new(),
@@ -105,11 +170,55 @@ impl BuildOptions {
             );
         };
 
+        let target = Target::from_target_triple(self.target.clone())?;
+
+        let mut cargo_extra_args = split_extra_args(&self.cargo_extra_args)?;
+        if let Some(target) = self.target {
+            cargo_extra_args.extend_from_slice(&["--target".to_string(), target]);
+        }
+
+        let cargo_metadata_extra_args = extra_feature_args(&cargo_extra_args);
+
+        let cargo_metadata = MetadataCommand::new()
+            .manifest_path(&manifest_file)
+            .other_options(cargo_metadata_extra_args.clone())
+            .exec()
+            .context("Cargo metadata failed. Do you have cargo in your PATH?")?;
+
+        // In a workspace, we switch to the manifest of the selected member. cargo metadata has to
+        // run again so that the member becomes the root of the dependency graph
+        let (manifest_file, cargo_metadata) = match self.package {
+            Some(ref package) => {
+                let manifest_file = find_workspace_member(&cargo_metadata, package)?;
+                let cargo_metadata = MetadataCommand::new()
+                    .manifest_path(&manifest_file)
+                    .other_options(cargo_metadata_extra_args)
+                    .exec()
+                    .context("Cargo metadata failed. Do you have cargo in your PATH?")?;
+                (manifest_file, cargo_metadata)
+            }
+            None => {
+                let root = cargo_metadata
+                    .resolve
+                    .as_ref()
+                    .and_then(|resolve| resolve.root.as_ref());
+                if root.is_none() {
+                    bail!(
+                        "{} is a virtual manifest of a workspace. \
+                         Please select the package to build with --package",
+                        manifest_file.display()
+                    );
+                }
+                (manifest_file, cargo_metadata)
+            }
+        };
+
         let cargo_toml = CargoToml::from_path(&manifest_file)?;
         let manifest_dir = manifest_file.parent().unwrap();
         let metadata21 = Metadata21::from_cargo_toml(&cargo_toml, &manifest_dir)
             .context("Failed to parse Cargo.toml into python metadata")?;
         let scripts = cargo_toml.scripts();
+        let crate_name = cargo_toml.package.name.clone();
 
         // If the package name contains minuses, you must declare a module with
         // underscores as lib name
@@ -122,21 +231,6 @@ impl BuildOptions {
 
         let project_layout = ProjectLayout::determine(manifest_dir, &module_name)?;
 
-        let target = Target::from_target_triple(self.target.clone())?;
-
-        let mut cargo_extra_args = split_extra_args(&self.cargo_extra_args)?;
-        if let Some(target) = self.target {
-            cargo_extra_args.extend_from_slice(&["--target".to_string(), target]);
-        }
-
-        let cargo_metadata_extra_args = extra_feature_args(&cargo_extra_args);
-
-        let cargo_metadata = MetadataCommand::new()
-            .manifest_path(&self.manifest_path)
-            .other_options(cargo_metadata_extra_args)
-            .exec()
-            .context("Cargo metadata failed. Do you have cargo in your PATH?")?;
-
         let wheel_dir = match self.out {
             Some(ref dir) => dir.clone(),
             None => PathBuf::from(&cargo_metadata.target_directory).join("wheels"),
@@ -144,6 +238,10 @@ impl BuildOptions {
 
         let bridge = find_bridge(&cargo_metadata, self.bindings.as_deref())?;
 
+        if bridge == BridgeModel::Bin && self.repair {
+            bail!("--repair is not supported for binaries");
+        }
+
         if bridge != BridgeModel::Bin && module_name.contains('-') {
             bail!(
                 "The module name must not contains a minus \
@@ -153,40 +251,184 @@ impl BuildOptions {
 
         let interpreter = match self.interpreter {
             // Only build a source ditribution
-            Some(ref interpreter) if interpreter.is_empty() => vec![],
+            Some(ref interpreter)
+                if interpreter.is_empty() && self.interpreter_sysconfig.is_empty() =>
+            {
+                vec![]
+            }
             // User given list of interpreters
-            Some(interpreter) => find_interpreter(&bridge, &interpreter, &target)?,
-            // Auto-detect interpreters
-            None => find_interpreter(&bridge, &[], &target)?,
+            Some(interpreter) => {
+                find_interpreter(&bridge, &interpreter, &self.interpreter_sysconfig, &target)?
+            }
+            // Auto-detect interpreters, unless there are sysconfigs
+            None => find_interpreter(&bridge, &[], &self.interpreter_sysconfig, &target)?,
         };
 
         let rustc_extra_args = split_extra_args(&self.rustc_extra_args)?;
 
         let manylinux = if self.skip_auditwheel {
             eprintln!("⚠ --skip-auditwheel is deprecated, use --manylinux=1-unchecked");
-            Manylinux::Manylinux1Unchecked
+            target.get_default_manylinux(false)
         } else {
             self.manylinux
+                .unwrap_or_else(|| target.get_default_manylinux(true))
+        };
+
+        // musl targets get musllinux tags, so the glibc based policies are mapped to their
+        // musllinux equivalent
+        let manylinux = if target.is_musl_libc() {
+            manylinux.for_musl()
+        } else if manylinux.musl_version().is_some() {
+            bail!(
+                "The {} policy is only supported for musl targets",
+                manylinux
+            );
+        } else {
+            manylinux
+        };
+
+        if self.split_debuginfo && !target.is_linux() {
+            bail!("--split-debuginfo is only supported for linux targets");
+        }
+
+        let bins = if bridge == BridgeModel::Bin {
+            find_bins(&cargo_metadata, &self.bins)?
+        } else if !self.bins.is_empty() {
+            bail!("--bin can only be used with bin bindings");
+        } else {
+            Vec::new()
+        };
+
+        let mut variants = cargo_toml.feature_variants();
+        let selected_variant = match self.variant {
+            Some(ref name) => {
+                let position = variants
+                    .iter()
+                    .position(|(variant, _)| variant == name)
+                    .ok_or_else(|| {
+                        format_err!(
+                            "There is no variant called {} in [package.metadata.maturin.variants]",
+                            name
+                        )
+                    })?;
+                Some(variants.remove(position))
+            }
+            None => None,
         };
 
-        Ok(BuildContext {
+        // The bindings are detected from the dependencies, which the features of a variant can
+        // change, but the interpreters were found for the bindings of the default features
+        for (name, variant) in variants.iter().chain(selected_variant.as_ref()) {
+            let variant_args = variant.cargo_args();
+            if variant_args.is_empty() {
+                continue;
+            }
+            let mut args = cargo_extra_args.clone();
+            args.extend(variant_args);
+            let variant_metadata = MetadataCommand::new()
+                .manifest_path(&manifest_file)
+                .other_options(extra_feature_args(&args))
+                .exec()
+                .context("Cargo metadata failed. Do you have cargo in your PATH?")?;
+            let variant_bridge = find_bridge(&variant_metadata, self.bindings.as_deref())?;
+            if variant_bridge != bridge {
+                bail!(
+                    "The features of the {} variant change the bindings from {:?} to {:?}, \
+                     which isn't supported. Please build it as a separate project",
+                    name,
+                    bridge,
+                    variant_bridge
+                );
+            }
+        }
+
+        let mut build_context = BuildContext {
             target,
             bridge,
             project_layout,
             metadata21,
             scripts,
             module_name,
-            manifest_path: self.manifest_path,
+            crate_name,
+            manifest_path: manifest_file,
             out: wheel_dir,
             release,
+            profile: self.profile,
+            bins,
             strip,
+            split_debuginfo: self.split_debuginfo,
             manylinux,
+            repair: self.repair,
+            allow_libpython_link: self.allow_libpython_link,
+            audit_exports: self.audit_exports,
+            max_unexpected_exports: self.max_unexpected_exports,
+            jobs: self.jobs,
             cargo_extra_args,
             rustc_extra_args,
             interpreter,
             cargo_metadata,
-        })
+            variants,
+        };
+
+        if let Some((name, variant)) = selected_variant {
+            build_context.variants = Vec::new();
+            build_context.apply_variant(&name, &variant)?;
+        }
+
+        Ok(build_context)
+    }
+}
+
+/// Returns the selected binaries of the root package, or all of them if none were selected
+fn find_bins(cargo_metadata: &Metadata, selected: &[String]) -> Result<Vec<String>> {
+    let root = cargo_metadata
+        .resolve
+        .as_ref()
+        .and_then(|resolve| resolve.root.as_ref())
+        .ok_or_else(|| format_err!("Cargo metadata didn't resolve the root package"))?;
+    let package = &cargo_metadata[root];
+    let available: Vec<String> = package
+        .targets
+        .iter()
+        .filter(|target| target.kind.iter().any(|kind| kind == "bin"))
+        .map(|target| target.name.clone())
+        .collect();
+
+    if available.is_empty() {
+        bail!("{} doesn't have any binaries", package.name);
+    }
+    if selected.is_empty() {
+        return Ok(available);
     }
+    for bin in selected {
+        if !available.contains(bin) {
+            bail!(
+                "{} doesn't have a binary called {}. The available binaries are: {}",
+                package.name,
+                bin,
+                available.join(", ")
+            );
+        }
+    }
+    Ok(selected.to_vec())
+}
+
+/// Returns the path to the Cargo.toml of the workspace member with the given name
+fn find_workspace_member(cargo_metadata: &Metadata, package: &str) -> Result<PathBuf> {
+    cargo_metadata
+        .packages
+        .iter()
+        .find(|member| {
+            member.name == package && cargo_metadata.workspace_members.contains(&member.id)
+        })
+        .map(|member| member.manifest_path.clone())
+        .ok_or_else(|| {
+            format_err!(
+                "There is no package called {} in the workspace at {}",
+                package,
+                cargo_metadata.workspace_root.display()
+            )
+        })
 }
 
 /// Tries to determine the [BridgeModel] for the target crate
@@ -196,11 +438,20 @@ pub fn find_bridge(cargo_metadata: &Metadata, bridge: Option<&str>) -> Result<Br
         .as_ref()
         .ok_or_else(|| format_err!("Expected to get a dependency graph from cargo"))?;
 
-    let deps: HashMap<&str, &Node> = resolve
-        .nodes
-        .iter()
-        .map(|node| (cargo_metadata[&node.id].name.as_ref(), node))
-        .collect();
+    // In a workspace, the graph contains the dependencies of all members, so we only look at the
+    // packages the root package depends on
+    let nodes: HashMap<&PackageId, &Node> =
+        resolve.nodes.iter().map(|node| (&node.id, node)).collect();
+    let mut deps: HashMap<&str, &Node> = HashMap::new();
+    let mut seen: HashSet<&PackageId> = HashSet::new();
+    let mut stack: Vec<&PackageId> = resolve.root.iter().collect();
+    while let Some(id) = stack.pop() {
+        if seen.insert(id) {
+            let node = nodes[id];
+            deps.entry(cargo_metadata[id].name.as_ref()).or_insert(node);
+            stack.extend(&node.dependencies);
+        }
+    }
 
     if let Some(bindings) = bridge {
         if bindings == "cffi" {
@@ -215,6 +466,12 @@ pub fn find_bridge(cargo_metadata: &Metadata, bridge: Option<&str>) -> Result<Br
                 );
             }
 
+            if bindings == "pyo3" {
+                if let Some((major, minor)) = find_abi3_version(&deps["pyo3"].features) {
+                    return Ok(BridgeModel::BindingsAbi3(major, minor));
+                }
+            }
+
             Ok(BridgeModel::Bindings(bindings.to_string()))
         }
     } else if let Some(node) = deps.get("pyo3") {
@@ -228,6 +485,13 @@ pub fn find_bridge(cargo_metadata: &Metadata, bridge: Option<&str>) -> Result<Br
                 version
             );
         }
+        if let Some((major, minor)) = find_abi3_version(&node.features) {
+            println!(
+                "🔗 Found pyo3's abi3 feature, building one wheel for CPython {}.{}+",
+                major, minor
+            );
+            return Ok(BridgeModel::BindingsAbi3(major, minor));
+        }
         Ok(BridgeModel::Bindings("pyo3".to_string()))
     } else if deps.contains_key("cpython") {
         println!("🔗 Found rust-cpython bindings");
@@ -261,23 +525,56 @@ pub fn find_bridge(cargo_metadata: &Metadata, bridge: Option<&str>) -> Result<Br
     }
 }
 
+/// Returns the minimum python version of the stable ABI if one of pyo3's `abi3` or `abi3-py3x`
+/// features is activated. The lowest `abi3-py3x` wins since pyo3 activates the features of all
+/// later versions too, while plain `abi3` means every python version maturin supports
+fn find_abi3_version(features: &[String]) -> Option<(u8, u8)> {
+    let minimum_minor = features
+        .iter()
+        .filter(|feature| feature.starts_with("abi3-py3"))
+        .filter_map(|feature| feature.trim_start_matches("abi3-py3").parse::<u8>().ok())
+        .min();
+    match minimum_minor {
+        Some(minor) => Some((3, minor)),
+        None if features.iter().any(|feature| feature == "abi3") => Some((3, 5)),
+        None => None,
+    }
+}
+
+/// Checks the interpreters given as executables or sysconfigs. If there are neither, all
+/// installed interpreters are used
+fn find_bindings_interpreters(
+    bridge: &BridgeModel,
+    interpreter: &[PathBuf],
+    interpreter_sysconfig: &[PathBuf],
+    target: &Target,
+) -> Result<Vec<PythonInterpreter>> {
+    if interpreter.is_empty() && interpreter_sysconfig.is_empty() {
+        return PythonInterpreter::find_all(target, bridge)
+            .context("Finding python interpreters failed");
+    }
+    let mut found = PythonInterpreter::check_executables(interpreter, target, bridge)
+        .context("The given list of python interpreters is invalid")?;
+    found.extend(
+        PythonInterpreter::from_sysconfigs(interpreter_sysconfig, target, bridge)
+            .context("The given list of python sysconfigs is invalid")?,
+    );
+    Ok(found)
+}
+
 /// Finds the appropriate amount for python versions for each [BridgeModel].
 ///
-/// This means all for bindings, one for cffi and zero for bin.
+/// This means all for bindings, one for cffi and abi3 bindings and zero for bin.
 pub fn find_interpreter(
     bridge: &BridgeModel,
     interpreter: &[PathBuf],
+    interpreter_sysconfig: &[PathBuf],
     target: &Target,
 ) -> Result<Vec<PythonInterpreter>> {
     match bridge {
         BridgeModel::Bindings(_) => {
-            let interpreter = if !interpreter.is_empty() {
-                PythonInterpreter::check_executables(&interpreter, &target, &bridge)
-                    .context("The given list of python interpreters is invalid")?
-            } else {
-                PythonInterpreter::find_all(&target, &bridge)
-                    .context("Finding python interpreters failed")?
-            };
+            let interpreter =
+                find_bindings_interpreters(bridge, interpreter, interpreter_sysconfig, target)?;
 
             if interpreter.is_empty() {
                 bail!("Couldn't find any python interpreters. Please specify at least one with -i");
@@ -294,7 +591,39 @@ pub fn find_interpreter(
 
             Ok(interpreter)
         }
+        BridgeModel::BindingsAbi3(major, minor) => {
+            let found =
+                find_bindings_interpreters(bridge, interpreter, interpreter_sysconfig, target)?;
+
+            // The library only needs to be compiled once, with any CPython version the abi3
+            // wheel supports
+            let interpreter = found
+                .into_iter()
+                .find(|interpreter| {
+                    interpreter.interpreter == Interpreter::CPython
+                        && (interpreter.major, interpreter.minor)
+                            >= (*major as usize, *minor as usize)
+                })
+                .ok_or_else(|| {
+                    format_err!(
+                        "Couldn't find a CPython interpreter >= {}.{} to build the abi3 wheel. \
+                         Please specify one with -i",
+                        major,
+                        minor
+                    )
+                })?;
+
+            println!("🐍 Using {} to build the abi3 wheel", interpreter);
+
+            Ok(vec![interpreter])
+        }
         BridgeModel::Cffi => {
+            if !interpreter_sysconfig.is_empty() {
+                bail!(
+                    "cffi bindings are generated by running python, \
+                     so they can't be built with --interpreter-sysconfig"
+                );
+            }
             let executable = if interpreter.is_empty() {
                 target.get_python()
             } else if interpreter.len() == 1 {
@@ -368,6 +697,32 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn test_default_manylinux() {
+        let build_context = |target: &str| {
+            BuildOptions {
+                manifest_path: PathBuf::from("test-crates/hello-world/Cargo.toml"),
+                target: Some(target.to_string()),
+                ..Default::default()
+            }
+            .into_build_context(false, false)
+            .unwrap()
+        };
+        // manylinux1 doesn't exist for aarch64 and armv7
+        assert_eq!(
+            build_context("aarch64-unknown-linux-gnu").manylinux,
+            Manylinux::Manylinux2014
+        );
+        assert_eq!(
+            build_context("armv7-unknown-linux-gnueabihf").manylinux,
+            Manylinux::Manylinux2014
+        );
+        assert_eq!(
+            build_context("x86_64-unknown-linux-gnu").manylinux,
+            Manylinux::Manylinux1
+        );
+    }
+
     #[test]
     fn test_find_bridge_pyo3() {
         let pyo3_pure = MetadataCommand::new()
@@ -443,6 +798,68 @@ mod test {
         assert!(find_bridge(&hello_world, Some("pyo3")).is_err());
     }
 
+    #[test]
+    fn test_find_abi3_version() {
+        let features = |features: &[&str]| -> Vec<String> {
+            features.iter().map(ToString::to_string).collect()
+        };
+        assert_eq!(find_abi3_version(&features(&["extension-module"])), None);
+        assert_eq!(find_abi3_version(&features(&["abi3"])), Some((3, 5)));
+        assert_eq!(
+            find_abi3_version(&features(&["abi3", "abi3-py38", "abi3-py37", "abi3-py39"])),
+            Some((3, 7))
+        );
+        assert_eq!(
+            find_abi3_version(&features(&["abi3-py310", "abi3"])),
+            Some((3, 10))
+        );
+    }
+
+    #[test]
+    fn test_workspace_package() {
+        let mut options = BuildOptions::default();
+        options.manifest_path = Path::new("test-crates/workspace").join("Cargo.toml");
+        options.bindings = Some("bin".to_string());
+        assert!(options.clone().into_build_context(false, false).is_err());
+
+        options.package = Some("hello-member".to_string());
+        let context = options.clone().into_build_context(false, false).unwrap();
+        assert_eq!(context.crate_name, "hello-member");
+        assert!(context
+            .manifest_path
+            .ends_with(Path::new("crates").join("hello-member").join("Cargo.toml")));
+        // The member uses the target directory of the workspace
+        assert!(context
+            .cargo_metadata
+            .target_directory
+            .ends_with(Path::new("workspace").join("target")));
+
+        options.package = Some("missing-member".to_string());
+        assert!(options.into_build_context(false, false).is_err());
+    }
+
+    #[test]
+    fn test_bins() {
+        let mut options = BuildOptions::default();
+        options.bindings = Some("bin".to_string());
+        let context = options.clone().into_build_context(false, false).unwrap();
+        assert_eq!(context.bins, vec!["maturin"]);
+
+        options.bins = vec!["maturin".to_string()];
+        options.profile = Some("dist".to_string());
+        let context = options.clone().into_build_context(false, false).unwrap();
+        assert_eq!(context.bins, vec!["maturin"]);
+        assert_eq!(context.profile, Some("dist".to_string()));
+
+        options.bins = vec!["missing".to_string()];
+        assert!(options.clone().into_build_context(false, false).is_err());
+
+        options.bins = vec!["maturin".to_string()];
+        options.manifest_path = Path::new("test-crates/pyo3-pure").join("Cargo.toml");
+        options.bindings = None;
+        assert!(options.into_build_context(false, false).is_err());
+    }
+
     #[test]
     fn test_argument_splitting() {
         let mut options = BuildOptions::default();
@@ -1,6 +1,6 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::Path;
 
@@ -84,6 +84,23 @@ impl CargoToml {
         }
     }
 
+    /// Returns the feature variants from `[package.metadata.maturin.variants]`, ordered by name
+    pub fn feature_variants(&self) -> Vec<(String, FeatureVariant)> {
+        match self.package.metadata {
+            Some(CargoTomlMetadata {
+                maturin:
+                    Some(RemainingCoreMetadata {
+                        variants: Some(ref variants),
+                        ..
+                    }),
+            }) => variants
+                .iter()
+                .map(|(name, variant)| (name.clone(), variant.clone()))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
     /// Returns the value of `[project.metadata.maturin]` or an empty stub
     pub fn remaining_core_metadata(&self) -> RemainingCoreMetadata {
         match &self.package.metadata {
@@ -119,6 +136,41 @@ pub struct RemainingCoreMetadata {
     pub requires_external: Option<Vec<String>>,
     pub project_url: Option<Vec<String>>,
     pub provides_extra: Option<Vec<String>>,
+    pub variants: Option<BTreeMap<String, FeatureVariant>>,
+}
+
+/// A set of cargo features to build separate wheels with, declared as
+/// `[package.metadata.maturin.variants.<name>]`
+///
+/// The wheels of a variant get `+<name>` as PEP 440 local version label, unless the variant has
+/// its own distribution name
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub struct FeatureVariant {
+    /// The features to activate
+    #[serde(default)]
+    pub features: Vec<String>,
+    /// Don't activate the default features
+    #[serde(default)]
+    pub no_default_features: bool,
+    /// A distribution name for the wheels of this variant, instead of the local version label
+    pub name: Option<String>,
+}
+
+impl FeatureVariant {
+    /// The arguments that select the features of the variant for cargo
+    pub fn cargo_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.no_default_features {
+            args.push("--no-default-features".to_string());
+        }
+        if !self.features.is_empty() {
+            args.push("--features".to_string());
+            args.push(self.features.join(","));
+        }
+        args
+    }
 }
 
 #[cfg(test)]
@@ -148,6 +200,13 @@ mod test {
             [package.metadata.maturin]
             classifier = ["Programming Language :: Python"]
             requires-dist = ["flask~=1.1.0", "toml==0.10.0"]
+
+            [package.metadata.maturin.variants.fast]
+            features = ["simd"]
+
+            [package.metadata.maturin.variants.compat]
+            no-default-features = true
+            name = "info-project-compat"
         "#
         );
 
@@ -166,5 +225,29 @@ mod test {
             cargo_toml.remaining_core_metadata().requires_dist,
             requires_dist
         );
+        assert_eq!(
+            cargo_toml.feature_variants(),
+            vec![
+                (
+                    "compat".to_string(),
+                    FeatureVariant {
+                        features: Vec::new(),
+                        no_default_features: true,
+                        name: Some("info-project-compat".to_string()),
+                    }
+                ),
+                (
+                    "fast".to_string(),
+                    FeatureVariant {
+                        features: vec!["simd".to_string()],
+                        no_default_features: false,
+                        name: None,
+                    }
+                ),
+            ]
+        );
+        let variants = cargo_toml.feature_variants();
+        assert_eq!(variants[0].1.cargo_args(), vec!["--no-default-features"]);
+        assert_eq!(variants[1].1.cargo_args(), vec!["--features", "simd"]);
     }
 }
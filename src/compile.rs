@@ -2,37 +2,95 @@ use crate::build_context::BridgeModel;
 use crate::BuildContext;
 use crate::PythonInterpreter;
 use anyhow::{bail, Context, Result};
+use cargo_metadata::diagnostic::{Diagnostic, DiagnosticLevel};
+use goblin::elf::section_header::SHN_UNDEF;
+use goblin::elf::sym::{STB_GLOBAL, STB_WEAK, STT_FUNC, STT_OBJECT, STV_DEFAULT, STV_PROTECTED};
+use goblin::mach::{Mach, MachO};
 use std::collections::HashMap;
-use std::fs::File;
+use std::env;
+use std::fs::{self, File};
 use std::io::{BufReader, Read};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::str;
 
 /// Builds the rust crate into a native module (i.e. an .so or .dll) for a
 /// specific python version. Returns a mapping from crate type (e.g. cdylib)
-/// to artifact location.
+/// to artifact location for each target that was built, i.e. one for each selected binary
+/// of a bin crate and a single one for the library otherwise.
 pub fn compile(
     context: &BuildContext,
     python_interpreter: Option<&PythonInterpreter>,
     bindings_crate: &BridgeModel,
+) -> Result<Vec<HashMap<String, PathBuf>>> {
+    match bindings_crate {
+        // The rustc extra args can only be passed to a single target, so we need one cargo
+        // invocation for every binary
+        BridgeModel::Bin => context
+            .bins
+            .iter()
+            .map(|bin| compile_target(context, python_interpreter, bindings_crate, &["--bin", bin]))
+            .collect(),
+        BridgeModel::Cffi | BridgeModel::Bindings(_) | BridgeModel::BindingsAbi3(..) => {
+            Ok(vec![compile_target(
+                context,
+                python_interpreter,
+                bindings_crate,
+                &["--lib"],
+            )?])
+        }
+    }
+}
+
+/// Runs `cargo rustc` for a single target, which is selected by `target_args`
+fn compile_target(
+    context: &BuildContext,
+    python_interpreter: Option<&PythonInterpreter>,
+    bindings_crate: &BridgeModel,
+    target_args: &[&str],
 ) -> Result<HashMap<String, PathBuf>> {
     let mut shared_args = vec!["--manifest-path", context.manifest_path.to_str().unwrap()];
 
-    // We need to pass --bins / --lib to set the rustc extra args later
-    // TODO: What do we do when there are multiple bin targets?
-    match bindings_crate {
-        BridgeModel::Bin => shared_args.push("--bins"),
-        BridgeModel::Cffi | BridgeModel::Bindings(_) => shared_args.push("--lib"),
+    // pyo3's build script reruns when PYTHON_SYS_EXECUTABLE changes, which means building for one
+    // interpreter would invalidate the artifacts of all other interpreters. We avoid that by
+    // giving every interpreter its own target directory, unless the user chose one
+    let target_dir = match (bindings_crate, python_interpreter) {
+        (BridgeModel::Bindings(_), Some(python_interpreter))
+            if !context
+                .cargo_extra_args
+                .iter()
+                .any(|arg| arg.starts_with("--target-dir")) =>
+        {
+            Some(interpreter_target_dir(
+                &context.cargo_metadata.target_directory,
+                python_interpreter,
+            ))
+        }
+        _ => None,
+    };
+    if let Some(target_dir) = &target_dir {
+        shared_args.extend(&["--target-dir", target_dir.to_str().unwrap()]);
     }
 
+    // We need to pass --bin / --lib to set the rustc extra args later
+    shared_args.extend(target_args);
+
     shared_args.extend(context.cargo_extra_args.iter().map(String::as_str));
 
-    if context.release {
-        shared_args.push("--release");
+    match context.profile {
+        Some(ref profile) => shared_args.extend(&["--profile", profile]),
+        None if context.release => shared_args.push("--release"),
+        None => {}
     }
 
-    let cargo_args = vec!["rustc", "--message-format", "json"];
+    // cargo can render the diagnostics with colors inside the json messages, which we only want
+    // when we print them to a terminal
+    let message_format = if atty::is(atty::Stream::Stderr) {
+        "json-diagnostic-rendered-ansi"
+    } else {
+        "json"
+    };
+    let cargo_args = vec!["rustc", "--message-format", message_format];
 
     let mut rustc_args: Vec<&str> = context
         .rustc_extra_args
@@ -41,13 +99,32 @@ pub fn compile(
         .collect();
 
     if context.target.is_macos() {
-        if let BridgeModel::Bindings(_) = bindings_crate {
+        if let BridgeModel::Bindings(_) | BridgeModel::BindingsAbi3(..) = bindings_crate {
             let mac_args = &["-C", "link-arg=-undefined", "-C", "link-arg=dynamic_lookup"];
             rustc_args.extend(mac_args);
         }
     }
 
-    if context.strip {
+    if context.split_debuginfo {
+        // The debug info is split off and the packaged artifact is stripped afterwards, which
+        // needs the debug info and a build-id to match the two. A debug info level from the
+        // rustc arguments or RUSTFLAGS is kept, but the one of the profile is overridden.
+        let rustflags = env::var("RUSTFLAGS").unwrap_or_default();
+        let user_args: Vec<&str> = rustc_args
+            .iter()
+            .copied()
+            .chain(rustflags.split_whitespace())
+            .collect();
+        if !user_args
+            .iter()
+            .any(|arg| arg.contains("debuginfo=") || *arg == "-g")
+        {
+            rustc_args.extend(&["-C", "debuginfo=2"]);
+        }
+        if !user_args.iter().any(|arg| arg.contains("--build-id")) {
+            rustc_args.extend(&["-C", "link-arg=-Wl,--build-id"]);
+        }
+    } else if context.strip {
         rustc_args.extend(&["-C", "link-arg=-s"]);
     }
 
@@ -66,17 +143,28 @@ pub fn compile(
         .args(&build_args)
         // We need to capture the json messages
         .stdout(Stdio::piped())
-        // We can't get colored human and json messages from rustc as they are mutually exclusive,
-        // but forwarding stderr is still useful in case there some non-json error
+        // The diagnostics are part of the json messages, but forwarding stderr is still useful
+        // for cargo's progress and in case there some non-json error
         .stderr(Stdio::inherit());
 
     if let Some(python_interpreter) = python_interpreter {
-        build_command.env("PYTHON_SYS_EXECUTABLE", &python_interpreter.executable);
+        if python_interpreter.sysconfig.is_some() {
+            // We can't run the interpreter, so pyo3 needs to read its configuration from the
+            // sysconfig. A PYO3_CROSS_LIB_DIR set by the user takes precedence
+            for (key, value) in python_interpreter.pyo3_cross_env() {
+                if env::var_os(key).is_none() {
+                    build_command.env(key, value);
+                }
+            }
+        } else {
+            build_command.env("PYTHON_SYS_EXECUTABLE", &python_interpreter.executable);
+        }
     }
 
     let mut cargo_build = build_command.spawn().context("Failed to run cargo")?;
 
     let mut artifacts = HashMap::new();
+    let mut diagnostics = DiagnosticCounts::default();
 
     let stream = cargo_build
         .stdout
@@ -88,7 +176,7 @@ pub fn compile(
                 let crate_name = &context.cargo_metadata[&artifact.package_id].name;
 
                 // Extract the location of the .so/.dll/etc. from cargo's json output
-                if crate_name == &context.metadata21.name {
+                if crate_name == &context.crate_name {
                     let tuples = artifact
                         .target
                         .crate_types
@@ -100,12 +188,20 @@ pub fn compile(
                 }
             }
             cargo_metadata::Message::CompilerMessage(msg) => {
-                println!("{}", msg.message);
+                match msg.message.rendered {
+                    Some(ref rendered) => eprint!("{}", rendered),
+                    None => eprintln!("{}", msg.message.message),
+                }
+                diagnostics.add(&msg.message);
             }
             _ => (),
         }
     }
 
+    if let Some(summary) = diagnostics.summary() {
+        eprintln!("{}", summary);
+    }
+
     let status = cargo_build
         .wait()
         .expect("Failed to wait on cargo child process");
@@ -121,42 +217,405 @@ pub fn compile(
     Ok(artifacts)
 }
 
+/// The number of warnings and errors cargo reported during a build
+#[derive(Debug, Default, PartialEq)]
+struct DiagnosticCounts {
+    warnings: usize,
+    errors: usize,
+}
+
+impl DiagnosticCounts {
+    /// Counts the diagnostic, unless it's one of rustc's own summaries such as
+    /// "2 warnings emitted" or "aborting due to previous error"
+    fn add(&mut self, diagnostic: &Diagnostic) {
+        let is_summary = diagnostic.spans.is_empty()
+            && (diagnostic.message.starts_with("aborting due to")
+                || diagnostic.message.ends_with(" emitted"));
+        if is_summary {
+            return;
+        }
+        match diagnostic.level {
+            DiagnosticLevel::Warning => self.warnings += 1,
+            DiagnosticLevel::Error | DiagnosticLevel::Ice => self.errors += 1,
+            _ => {}
+        }
+    }
+
+    /// A line such as "⚠  cargo reported 2 warnings and 1 error", if there was anything
+    fn summary(&self) -> Option<String> {
+        let plural = |count: usize, name: &str| {
+            if count == 1 {
+                format!("1 {}", name)
+            } else {
+                format!("{} {}s", count, name)
+            }
+        };
+        match (self.warnings, self.errors) {
+            (0, 0) => None,
+            (warnings, 0) => Some(format!("⚠  cargo reported {}", plural(warnings, "warning"))),
+            (0, errors) => Some(format!("💥 cargo reported {}", plural(errors, "error"))),
+            (warnings, errors) => Some(format!(
+                "💥 cargo reported {} and {}",
+                plural(warnings, "warning"),
+                plural(errors, "error")
+            )),
+        }
+    }
+}
+
+/// Returns the cargo target directory for building against a specific interpreter, e.g.
+/// `target/maturin/cpython-3.8`
+fn interpreter_target_dir(target_dir: &Path, python_interpreter: &PythonInterpreter) -> PathBuf {
+    target_dir.join("maturin").join(format!(
+        "{}-{}.{}{}",
+        python_interpreter.interpreter.to_string().to_lowercase(),
+        python_interpreter.major,
+        python_interpreter.minor,
+        python_interpreter.abiflags
+    ))
+}
+
+/// Returns whether the native library, which may be an elf, Mach-O or PE file, exports a symbol
+/// with the given name
+fn exports_symbol(buffer: &[u8], symbol: &str) -> Result<bool> {
+    let found = match goblin::Object::parse(buffer)? {
+        goblin::Object::Elf(elf) => elf
+            .dynsyms
+            .iter()
+            .any(|dyn_sym| symbol == &elf.dynstrtab[dyn_sym.st_name]),
+        goblin::Object::Mach(Mach::Binary(macho)) => macho_exports_symbol(&macho, symbol)?,
+        goblin::Object::Mach(Mach::Fat(multi_arch)) => {
+            // The symbol must be there for every architecture
+            let mut found = true;
+            for macho in &multi_arch {
+                found &= macho_exports_symbol(&macho?, symbol)?;
+            }
+            found
+        }
+        goblin::Object::PE(pe) => pe.exports.iter().any(|export| export.name == Some(symbol)),
+        // We can't tell for anything else
+        _ => true,
+    };
+    Ok(found)
+}
+
+/// Searches the export trie of the Mach-O file, where C symbols have a leading underscore
+fn macho_exports_symbol(macho: &MachO, symbol: &str) -> Result<bool> {
+    let mangled = format!("_{}", symbol);
+    Ok(macho.exports()?.iter().any(|export| export.name == mangled))
+}
+
 /// Checks that the native library contains a function called `PyInit_<module name>` and warns
 /// if it's missing.
 ///
 /// That function is the python's entrypoint for loading native extensions, i.e. python will fail
 /// to import the module with error if it's missing or named incorrectly
 ///
-/// Currently the check is only run on linux
+/// The check works for elf (linux and freebsd), Mach-O (macOS) and PE (windows) libraries
 pub fn warn_missing_py_init(artifact: &PathBuf, module_name: &str) -> Result<()> {
     let py_init = format!("PyInit_{}", module_name);
     let mut fd = File::open(&artifact)?;
     let mut buffer = Vec::new();
     fd.read_to_end(&mut buffer)?;
-    let mut found = false;
-    match goblin::Object::parse(&buffer)? {
+
+    if !exports_symbol(&buffer, &py_init)? {
+        println!(
+            "⚠  Warning: Couldn't find the symbol `{}` in the native library. \
+             Python will fail to import this module. \
+             If you're using pyo3, check that `#[pymodule]` uses `{}` as module name",
+            py_init, module_name
+        )
+    }
+
+    Ok(())
+}
+
+/// Returns the names of all symbols the native library exports, sorted and without duplicates.
+/// The leading underscore of Mach-O symbols is removed
+fn exported_symbols(buffer: &[u8]) -> Result<Vec<String>> {
+    let mut symbols = Vec::new();
+    match goblin::Object::parse(buffer)? {
         goblin::Object::Elf(elf) => {
             for dyn_sym in elf.dynsyms.iter() {
-                if py_init == elf.dynstrtab[dyn_sym.st_name] {
-                    found = true;
-                    break;
+                // Only defined, globally visible functions and variables are exported
+                if dyn_sym.st_shndx == SHN_UNDEF as usize
+                    || ![STB_GLOBAL, STB_WEAK].contains(&dyn_sym.st_bind())
+                    || ![STT_FUNC, STT_OBJECT].contains(&dyn_sym.st_type())
+                    || ![STV_DEFAULT, STV_PROTECTED].contains(&dyn_sym.st_visibility())
+                {
+                    continue;
                 }
+                symbols.push(elf.dynstrtab[dyn_sym.st_name].to_string());
+            }
+        }
+        goblin::Object::Mach(Mach::Binary(macho)) => {
+            symbols.extend(macho_exported_symbols(&macho)?)
+        }
+        goblin::Object::Mach(Mach::Fat(multi_arch)) => {
+            for macho in &multi_arch {
+                symbols.extend(macho_exported_symbols(&macho?)?);
             }
         }
-        _ => {
-            // Currently, only linux is implemented
-            found = true
+        goblin::Object::PE(pe) => symbols.extend(
+            pe.exports
+                .iter()
+                .filter_map(|export| export.name.map(ToString::to_string)),
+        ),
+        // We can't tell for anything else
+        _ => {}
+    }
+    symbols.sort();
+    symbols.dedup();
+    Ok(symbols)
+}
+
+/// Lists the export trie of the Mach-O file with the leading underscore of C symbols removed.
+/// Only one underscore is removed, since `__foo` is the Mach-O name of the C symbol `_foo`
+fn macho_exported_symbols(macho: &MachO) -> Result<Vec<String>> {
+    Ok(macho
+        .exports()?
+        .into_iter()
+        .map(|export| {
+            export
+                .name
+                .strip_prefix('_')
+                .unwrap_or(&export.name)
+                .to_string()
+        })
+        .collect())
+}
+
+/// Lists the symbols the native library exports beyond the expected ones, which are
+/// `PyInit_<module name>` for pyo3 and rust-cpython or the functions and variables declared in the
+/// header for cffi. Unexpected exports are usually `#[no_mangle] pub` items from dependencies,
+/// which can clash with the symbols of other native modules loaded into the same process.
+///
+/// Fails if there are more unexpected exports than `max_unexpected_exports`
+pub fn audit_exports(
+    artifact: &Path,
+    expected: &[String],
+    max_unexpected_exports: Option<usize>,
+) -> Result<()> {
+    let buffer = fs::read(artifact).context(format!(
+        "Failed to read the native library {}",
+        artifact.display()
+    ))?;
+    let unexpected: Vec<String> = exported_symbols(&buffer)
+        .context("Failed to parse the native library")?
+        .into_iter()
+        .filter(|symbol| !expected.contains(symbol))
+        .collect();
+
+    if unexpected.is_empty() {
+        println!("🔍 The native library exports only the expected symbols");
+        return Ok(());
+    }
+
+    println!(
+        "⚠  Warning: The native library exports {} unexpected symbol(s):",
+        unexpected.len()
+    );
+    for symbol in &unexpected {
+        println!("   - {}", symbol);
+    }
+
+    if let Some(max_unexpected_exports) = max_unexpected_exports {
+        if unexpected.len() > max_unexpected_exports {
+            bail!(
+                "The native library exports {} unexpected symbols, but at most {} are allowed",
+                unexpected.len(),
+                max_unexpected_exports
+            );
         }
     }
 
-    if !found {
-        println!(
-            "⚠  Warning: Couldn't find the symbol `{}` in the native library. \
-             Python will fail to import this module. \
-             If you're using pyo3, check that `#[pymodule]` uses `{}` as module name",
-            py_init, module_name
-        )
+    Ok(())
+}
+
+/// Returns true for the file names of the shared libpython, e.g. `libpython3.8.so.1.0` or
+/// `libpython3.7m.so`
+fn is_libpython(library: &str) -> bool {
+    library.starts_with("libpython") && library.contains(".so")
+}
+
+/// Fails if the native library links libpython, which usually means that pyo3's
+/// `extension-module` feature isn't activated. Such a library only imports in interpreters that
+/// ship a shared libpython, but e.g. the python builds in the manylinux docker images are
+/// statically linked.
+///
+/// With `allow_libpython_link`, only a warning is printed. Currently the check is only run on
+/// linux
+pub fn check_libpython_link(artifact: &Path, allow_libpython_link: bool) -> Result<()> {
+    let mut fd = File::open(artifact).context(format!(
+        "Failed to open the native library {}",
+        artifact.display()
+    ))?;
+    let mut buffer = Vec::new();
+    fd.read_to_end(&mut buffer)?;
+    let object = goblin::Object::parse(&buffer).context("Failed to parse the native library")?;
+    let libpython = match object {
+        goblin::Object::Elf(elf) => elf
+            .libraries
+            .iter()
+            .find(|library| is_libpython(library))
+            .map(ToString::to_string),
+        // Currently, only linux is implemented
+        _ => None,
+    };
+
+    if let Some(libpython) = libpython {
+        let message = format!(
+            "The native library links {}, which makes it fail to import in python interpreters \
+             without a shared libpython, such as the ones in the manylinux docker images. \
+             If you're using pyo3, activate its `extension-module` feature",
+            libpython
+        );
+        if allow_libpython_link {
+            println!("⚠  Warning: {}", message);
+        } else {
+            bail!("{}. Pass --allow-libpython-link to ignore this", message);
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn diagnostic(level: &str, message: &str, with_span: bool) -> Diagnostic {
+        let spans = if with_span {
+            serde_json::json!([{
+                "file_name": "src/lib.rs",
+                "byte_start": 0,
+                "byte_end": 1,
+                "line_start": 1,
+                "line_end": 1,
+                "column_start": 1,
+                "column_end": 2,
+                "is_primary": true,
+                "text": [],
+                "label": null,
+                "suggested_replacement": null,
+                "suggestion_applicability": null,
+                "expansion": null
+            }])
+        } else {
+            serde_json::json!([])
+        };
+        serde_json::from_value(serde_json::json!({
+            "message": message,
+            "code": null,
+            "level": level,
+            "spans": spans,
+            "children": [],
+            "rendered": null
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_diagnostic_counts() {
+        let mut counts = DiagnosticCounts::default();
+        assert_eq!(counts.summary(), None);
+
+        counts.add(&diagnostic("warning", "unused variable: `x`", true));
+        counts.add(&diagnostic("warning", "1 warning emitted", false));
+        assert_eq!(
+            counts.summary(),
+            Some("⚠  cargo reported 1 warning".to_string())
+        );
+
+        counts.add(&diagnostic("warning", "unused import: `std::fs`", true));
+        counts.add(&diagnostic("error", "mismatched types", true));
+        counts.add(&diagnostic("note", "expected due to this", true));
+        counts.add(&diagnostic(
+            "error",
+            "aborting due to previous error; 2 warnings emitted",
+            false,
+        ));
+        assert_eq!(
+            counts,
+            DiagnosticCounts {
+                warnings: 2,
+                errors: 1
+            }
+        );
+        assert_eq!(
+            counts.summary(),
+            Some("💥 cargo reported 2 warnings and 1 error".to_string())
+        );
+    }
+
+    fn fixture_path(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data")
+            .join("py-init")
+            .join(name)
+    }
+
+    fn read_fixture(name: &str) -> Vec<u8> {
+        fs::read(fixture_path(name)).unwrap()
+    }
+
+    #[test]
+    fn test_exports_symbol_pe() {
+        let dll = read_fixture("pyinit_fixture.dll");
+        assert!(exports_symbol(&dll, "PyInit_fixture").unwrap());
+        assert!(!exports_symbol(&dll, "PyInit_other").unwrap());
+    }
+
+    #[test]
+    fn test_exports_symbol_macho() {
+        let dylib = read_fixture("pyinit_fixture.dylib");
+        assert!(exports_symbol(&dylib, "PyInit_fixture").unwrap());
+        assert!(!exports_symbol(&dylib, "PyInit_other").unwrap());
+    }
+
+    #[test]
+    fn test_exported_symbols() {
+        let dll = read_fixture("pyinit_fixture.dll");
+        assert_eq!(exported_symbols(&dll).unwrap(), vec!["PyInit_fixture"]);
+        let dylib = read_fixture("pyinit_fixture.dylib");
+        assert_eq!(exported_symbols(&dylib).unwrap(), vec!["PyInit_fixture"]);
+    }
+
+    #[test]
+    fn test_audit_exports() {
+        let path = fixture_path("pyinit_fixture.dll");
+        audit_exports(&path, &["PyInit_fixture".to_string()], Some(0)).unwrap();
+        audit_exports(&path, &[], Some(1)).unwrap();
+        assert!(audit_exports(&path, &[], Some(0)).is_err());
+        audit_exports(&path, &[], None).unwrap();
+    }
+
+    #[test]
+    fn test_interpreter_target_dir() {
+        let python_interpreter = PythonInterpreter {
+            major: 3,
+            minor: 7,
+            abiflags: "m".to_string(),
+            target: crate::Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string()))
+                .unwrap(),
+            executable: PathBuf::from("python3.7"),
+            ext_suffix: Some(".cpython-37m-x86_64-linux-gnu.so".to_string()),
+            interpreter: crate::python_interpreter::Interpreter::CPython,
+            abi_tag: Some("37m".to_string()),
+            sysconfig: None,
+        };
+        assert_eq!(
+            interpreter_target_dir(Path::new("target"), &python_interpreter),
+            Path::new("target").join("maturin").join("cpython-3.7m")
+        );
+    }
+
+    #[test]
+    fn test_is_libpython() {
+        assert!(is_libpython("libpython3.8.so.1.0"));
+        assert!(is_libpython("libpython3.7m.so"));
+        assert!(is_libpython("libpython3.so"));
+        assert!(!is_libpython("libc.so.6"));
+        assert!(!is_libpython("libpython3.8.a"));
+    }
+}
@@ -0,0 +1,212 @@
+//! Splits the debug info off the native libraries and binaries, so that the wheels stay small
+//! while crash reports from them can still be symbolized
+//!
+//! The debug info is saved in a `<wheel stem>.dbg.zip` next to each wheel. Inside, the debug file
+//! of every library or binary is stored as `.build-id/<xx>/<rest>.debug`, which is the layout of
+//! gdb's debug file directories, where `<xx><rest>` is the GNU build-id. The build-id stays in the
+//! stripped file, so a crash report can be matched to its debug info. The splitting is done with
+//! objcopy from binutils, which must be installed.
+
+use crate::module_writer::{source_date_epoch, zip_date_time};
+use anyhow::{bail, Context, Result};
+use goblin::elf::note::NT_GNU_BUILD_ID;
+use goblin::elf::Elf;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use zip::ZipWriter;
+
+/// The result of splitting the debug info off an artifact
+#[derive(Debug, Clone)]
+pub struct SplitDebugInfo {
+    /// The hex encoded GNU build-id of the artifact
+    pub build_id: String,
+    /// The stripped copy of the artifact, which goes into the wheel
+    pub stripped: PathBuf,
+    /// The debug info that was split off
+    pub debug_file: PathBuf,
+}
+
+/// Returns the hex encoded GNU build-id of the elf file, if it has one
+pub fn gnu_build_id(buffer: &[u8]) -> Result<Option<String>> {
+    let elf = Elf::parse(buffer).context("Failed to parse the elf file")?;
+    let notes = match elf.iter_note_headers(buffer) {
+        Some(notes) => notes,
+        None => return Ok(None),
+    };
+    for note in notes {
+        let note = note.context("Failed to parse the notes of the elf file")?;
+        if note.n_type == NT_GNU_BUILD_ID && note.name.trim_end_matches('\0') == "GNU" {
+            let hex: Vec<String> = note
+                .desc
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            return Ok(Some(hex.join("")));
+        }
+    }
+    Ok(None)
+}
+
+/// Runs objcopy with the given arguments
+fn objcopy(args: &[&OsStr]) -> Result<()> {
+    let output = Command::new("objcopy").args(args).output().context(
+        "Failed to run objcopy, which is required to split the debug info. Is binutils installed?",
+    )?;
+    if !output.status.success() {
+        bail!(
+            "objcopy failed: {}\n--- Stdout:\n{}\n--- Stderr:\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr),
+        );
+    }
+    Ok(())
+}
+
+/// Writes the debug info of the artifact and a stripped copy of it to a directory named after
+/// the build-id in `tempdir`. The artifact itself isn't modified, since cargo may reuse it for
+/// the next build
+pub fn split_debuginfo(artifact: &Path, tempdir: &Path) -> Result<SplitDebugInfo> {
+    let buffer = fs::read(artifact).context(format!("Failed to read {}", artifact.display()))?;
+    let build_id = gnu_build_id(&buffer).context(format!(
+        "Failed to read the build-id of {}",
+        artifact.display()
+    ))?;
+    let build_id = match build_id {
+        Some(build_id) => build_id,
+        None => bail!(
+            "{} doesn't have a GNU build-id, so its debug info couldn't be matched to it",
+            artifact.display()
+        ),
+    };
+
+    let dir = tempdir.join(&build_id);
+    fs::create_dir_all(&dir)?;
+    let file_name = artifact.file_name().unwrap();
+    let stripped = dir.join(file_name);
+    let debug_file = dir.join(format!("{}.debug", file_name.to_string_lossy()));
+
+    objcopy(&[
+        OsStr::new("--only-keep-debug"),
+        artifact.as_os_str(),
+        debug_file.as_os_str(),
+    ])
+    .context(format!(
+        "Failed to extract the debug info of {}",
+        artifact.display()
+    ))?;
+    let debuglink = format!("--add-gnu-debuglink={}", debug_file.display());
+    objcopy(&[
+        OsStr::new("--strip-debug"),
+        OsStr::new("--strip-unneeded"),
+        OsStr::new(&debuglink),
+        artifact.as_os_str(),
+        stripped.as_os_str(),
+    ])
+    .context(format!("Failed to strip {}", artifact.display()))?;
+
+    Ok(SplitDebugInfo {
+        build_id,
+        stripped,
+        debug_file,
+    })
+}
+
+/// Returns the path of the debug info archive for the wheel, i.e. `<wheel stem>.dbg.zip`
+pub fn debuginfo_archive_path(wheel_path: &Path) -> PathBuf {
+    let stem = wheel_path.file_stem().unwrap().to_string_lossy();
+    wheel_path.with_file_name(format!("{}.dbg.zip", stem))
+}
+
+/// Writes the debug info of the files in the wheel, given as their name and their split debug
+/// info, to the archive next to the wheel. The archive also contains a `BUILD-IDS` file with
+/// one `<build-id> <name>` line per file
+pub fn write_debuginfo_archive(
+    wheel_path: &Path,
+    debuginfo: &[(String, SplitDebugInfo)],
+) -> Result<PathBuf> {
+    let archive_path = debuginfo_archive_path(wheel_path);
+    let file = File::create(&archive_path).context(format!(
+        "Failed to create the debug info archive at {}",
+        archive_path.display()
+    ))?;
+    let mut zip = ZipWriter::new(file);
+    let options =
+        zip::write::FileOptions::default().last_modified_time(zip_date_time(source_date_epoch()?));
+
+    let mut build_ids = String::new();
+    for (name, split) in debuginfo {
+        let (prefix, rest) = split.build_id.split_at(2.min(split.build_id.len()));
+        zip.start_file(format!(".build-id/{}/{}.debug", prefix, rest), options)?;
+        zip.write_all(&fs::read(&split.debug_file)?)?;
+        build_ids.push_str(&format!("{} {}\n", split.build_id, name));
+    }
+    zip.start_file("BUILD-IDS", options)?;
+    zip.write_all(build_ids.as_bytes())?;
+    zip.finish()?;
+
+    Ok(archive_path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    #[test]
+    fn test_gnu_build_id() {
+        // The test binary itself is linked with a build-id by the default linkers on linux
+        if cfg!(target_os = "linux") {
+            let buffer = fs::read(env::current_exe().unwrap()).unwrap();
+            if let Some(build_id) = gnu_build_id(&buffer).unwrap() {
+                assert!(build_id.len() >= 16);
+                assert!(build_id.chars().all(|char| char.is_ascii_hexdigit()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_debuginfo_archive() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let debug_file = tempdir.path().join("libfoo.so.debug");
+        fs::write(&debug_file, b"debug").unwrap();
+        let wheel_path = tempdir
+            .path()
+            .join("foo-0.1.0-cp38-cp38-manylinux1_x86_64.whl");
+        let split = SplitDebugInfo {
+            build_id: "abcdef0123".to_string(),
+            stripped: tempdir.path().join("libfoo.so"),
+            debug_file,
+        };
+
+        let archive_path =
+            write_debuginfo_archive(&wheel_path, &[("foo".to_string(), split)]).unwrap();
+        assert_eq!(
+            archive_path,
+            tempdir
+                .path()
+                .join("foo-0.1.0-cp38-cp38-manylinux1_x86_64.dbg.zip")
+        );
+
+        let mut archive = ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        let mut contents = String::new();
+        archive
+            .by_name(".build-id/ab/cdef0123.debug")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "debug");
+        contents.clear();
+        archive
+            .by_name("BUILD-IDS")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "abcdef0123 foo\n");
+    }
+}
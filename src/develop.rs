@@ -26,12 +26,23 @@ pub fn develop(
     let python = target.get_venv_python(&venv_dir);
 
     let build_options = BuildOptions {
-        manylinux: Manylinux::Off,
+        manylinux: Some(Manylinux::Off),
         interpreter: Some(vec![target.get_python()]),
+        interpreter_sysconfig: Vec::new(),
         bindings,
         manifest_path: manifest_file.to_path_buf(),
         out: None,
         skip_auditwheel: false,
+        repair: false,
+        allow_libpython_link: false,
+        audit_exports: false,
+        max_unexpected_exports: None,
+        jobs: 1,
+        split_debuginfo: false,
+        variant: None,
+        package: None,
+        profile: None,
+        bins: Vec::new(),
         target: None,
         cargo_extra_args,
         rustc_extra_args,
@@ -52,21 +63,23 @@ pub fn develop(
         BridgeModel::Bin => {
             let artifacts = compile(&build_context, None, &BridgeModel::Bin).context(context)?;
 
-            let artifact = artifacts
-                .get("bin")
-                .ok_or_else(|| format_err!("Cargo didn't build a binary"))?;
+            for artifacts in artifacts {
+                let artifact = artifacts
+                    .get("bin")
+                    .ok_or_else(|| format_err!("Cargo didn't build a binary"))?;
 
-            // Copy the artifact into the same folder as pip and python
-            let bin_name = artifact.file_name().unwrap();
-            let bin_path = target.get_venv_bin_dir(&venv_dir).join(bin_name);
-            fs::copy(&artifact, &bin_path).context(format!(
-                "Failed to copy {} to {}",
-                artifact.display(),
-                bin_path.display()
-            ))?;
+                // Copy the artifact into the same folder as pip and python
+                let bin_name = artifact.file_name().unwrap();
+                let bin_path = target.get_venv_bin_dir(&venv_dir).join(bin_name);
+                fs::copy(&artifact, &bin_path).context(format!(
+                    "Failed to copy {} to {}",
+                    artifact.display(),
+                    bin_path.display()
+                ))?;
+            }
         }
         BridgeModel::Cffi => {
-            let artifact = build_context.compile_cdylib(None, None).context(context)?;
+            let (artifact, _) = build_context.compile_cdylib(None, None).context(context)?;
 
             builder.delete_dir(&build_context.module_name)?;
 
@@ -80,17 +93,22 @@ pub fn develop(
                 true,
             )?;
         }
-        BridgeModel::Bindings(_) => {
-            let artifact = build_context
+        BridgeModel::Bindings(_) | BridgeModel::BindingsAbi3(..) => {
+            let (artifact, _) = build_context
                 .compile_cdylib(Some(&interpreter), Some(&build_context.module_name))
                 .context(context)?;
 
+            let python_interpreter = match build_context.bridge {
+                BridgeModel::BindingsAbi3(..) => None,
+                _ => Some(&interpreter),
+            };
             write_bindings_module(
                 &mut builder,
                 &build_context.project_layout,
                 &build_context.module_name,
                 &artifact,
-                &interpreter,
+                python_interpreter,
+                &target,
                 true,
             )?;
         }
@@ -0,0 +1,147 @@
+//! Fingerprints of everything that goes into a wheel, so that wheels whose inputs didn't change
+//! since the last build can be reused instead of being written again
+//!
+//! The fingerprints are stored in a `.fingerprints` directory next to the wheels
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Incrementally hashes the inputs of a wheel
+pub struct Fingerprint {
+    hasher: Sha256,
+}
+
+impl Fingerprint {
+    /// Creates a new fingerprint, which already contains the maturin version since the wheel
+    /// contents can also change with maturin itself
+    pub fn new() -> Self {
+        let mut fingerprint = Fingerprint {
+            hasher: Sha256::new(),
+        };
+        fingerprint.add_bytes(env!("CARGO_PKG_VERSION").as_bytes());
+        fingerprint
+    }
+
+    /// Adds some bytes. The length is hashed too, so that the boundaries between inputs matter
+    pub fn add_bytes(&mut self, bytes: &[u8]) {
+        self.hasher.update((bytes.len() as u64).to_le_bytes());
+        self.hasher.update(bytes);
+    }
+
+    /// Adds a string
+    pub fn add_str(&mut self, value: &str) {
+        self.add_bytes(value.as_bytes());
+    }
+
+    /// Adds the json serialization of the value
+    pub fn add_serialized(&mut self, value: &impl Serialize) -> Result<()> {
+        self.add_bytes(&serde_json::to_vec(value)?);
+        Ok(())
+    }
+
+    /// Adds the contents of a file
+    pub fn add_file(&mut self, path: &Path) -> Result<()> {
+        let contents = fs::read(path).context(format!("Failed to read {}", path.display()))?;
+        self.add_bytes(&contents);
+        Ok(())
+    }
+
+    /// Adds the contents of a file, or a marker if there is no such file
+    pub fn add_optional_file(&mut self, path: &Path) -> Result<()> {
+        if path.is_file() {
+            self.add_file(path)
+        } else {
+            self.add_str("<missing>");
+            Ok(())
+        }
+    }
+
+    /// Adds the relative paths and the contents of all files in the directory
+    pub fn add_dir(&mut self, dir: &Path) -> Result<()> {
+        for entry in WalkDir::new(dir).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(dir)?;
+            self.add_str(&relative.to_string_lossy());
+            self.add_file(entry.path())?;
+        }
+        Ok(())
+    }
+
+    /// Returns the hex encoded hash
+    pub fn finish(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+/// Returns the file the fingerprint of the wheel is stored in
+fn fingerprint_file(output: &Path) -> PathBuf {
+    let file_name = output.file_name().unwrap().to_string_lossy();
+    output
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(".fingerprints")
+        .join(format!("{}.sha256", file_name))
+}
+
+/// Whether the output exists and was built from inputs with the same fingerprint
+pub fn is_up_to_date(output: &Path, fingerprint: &str) -> bool {
+    if !output.is_file() {
+        return false;
+    }
+    match fs::read_to_string(fingerprint_file(output)) {
+        Ok(stored) => stored.trim() == fingerprint,
+        Err(_) => false,
+    }
+}
+
+/// Records the fingerprint of the inputs of the output that was just built
+pub fn store_fingerprint(output: &Path, fingerprint: &str) -> Result<()> {
+    let file = fingerprint_file(output);
+    fs::create_dir_all(file.parent().unwrap())
+        .context("Failed to create the directory for the fingerprints")?;
+    fs::write(&file, fingerprint).context(format!(
+        "Failed to write the fingerprint to {}",
+        file.display()
+    ))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_boundaries() {
+        let mut first = Fingerprint::new();
+        first.add_str("ab");
+        first.add_str("c");
+        let mut second = Fingerprint::new();
+        second.add_str("a");
+        second.add_str("bc");
+        assert_ne!(first.finish(), second.finish());
+    }
+
+    #[test]
+    fn test_is_up_to_date() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let wheel = tempdir.path().join("foo-0.1.0-py3-none-any.whl");
+
+        // Without the wheel, there's nothing to reuse
+        store_fingerprint(&wheel, "abc").unwrap();
+        assert!(!is_up_to_date(&wheel, "abc"));
+
+        fs::write(&wheel, b"").unwrap();
+        assert!(is_up_to_date(&wheel, "abc"));
+        assert!(!is_up_to_date(&wheel, "def"));
+
+        store_fingerprint(&wheel, "def").unwrap();
+        assert!(is_up_to_date(&wheel, "def"));
+    }
+}
@@ -8,7 +8,8 @@
 //! Default features: auditwheel, log, upload, rustls
 //!
 //! - auditwheel: Reimplements the more important part of the auditwheel
-//! package in rust. A wheel is checked by default, unless deactivated by cli arguments
+//! package in rust. A wheel is checked by default, unless deactivated by cli arguments. Also
+//! adds `--repair`, which vendors external libraries using patchelf, and the audit command
 //!
 //! - log: Configures pretty-env-logger, even though maturin doesn't use logging itself.
 //!
@@ -26,6 +27,8 @@
 
 #![deny(missing_docs)]
 
+#[cfg(feature = "auditwheel")]
+pub use crate::audit::{audit_wheel, FileAudit, WheelAudit};
 #[cfg(feature = "auditwheel")]
 pub use crate::auditwheel::{auditwheel_rs, AuditWheelError};
 pub use crate::build_context::BridgeModel;
@@ -40,25 +43,33 @@ pub use crate::module_writer::{
 };
 pub use crate::python_interpreter::PythonInterpreter;
 pub use crate::target::{Manylinux, Target};
-pub use source_distribution::{get_pyproject_toml, source_distribution};
+pub use source_distribution::{
+    get_pyproject_toml, is_vendored, list_source_distribution, source_distribution,
+};
 #[cfg(feature = "upload")]
 pub use {
     crate::registry::Registry,
     crate::upload::{upload, UploadError},
 };
 
+#[cfg(feature = "auditwheel")]
+mod audit;
 #[cfg(feature = "auditwheel")]
 mod auditwheel;
 mod build_context;
 mod build_options;
 mod cargo_toml;
 mod compile;
+mod debuginfo;
 mod develop;
+mod fingerprint;
 mod metadata;
 mod module_writer;
 mod python_interpreter;
 #[cfg(feature = "upload")]
 mod registry;
+#[cfg(feature = "auditwheel")]
+mod repair;
 mod source_distribution;
 mod target;
 #[cfg(feature = "upload")]
@@ -10,9 +10,12 @@ use cargo_metadata::MetadataCommand;
 use human_panic::setup_panic;
 #[cfg(feature = "password-storage")]
 use keyring::{Keyring, KeyringError};
+#[cfg(feature = "auditwheel")]
+use maturin::audit_wheel;
 use maturin::{
-    develop, get_pyproject_toml, source_distribution, write_dist_info, BridgeModel, BuildOptions,
-    CargoToml, Metadata21, PathWriter, PythonInterpreter, Target,
+    develop, get_pyproject_toml, is_vendored, list_source_distribution, source_distribution,
+    write_dist_info, BridgeModel, BuildOptions, CargoToml, Metadata21, PathWriter,
+    PythonInterpreter, Target,
 };
 use std::path::PathBuf;
 use std::{env, fs};
@@ -127,6 +130,9 @@ enum Opt {
         /// Don't build a source distribution
         #[structopt(long = "no-sdist")]
         no_sdist: bool,
+        /// Vendor the dependencies into the source distribution, so that it can be built offline
+        #[structopt(long)]
+        vendor: bool,
     },
     #[cfg(feature = "upload")]
     #[structopt(name = "publish")]
@@ -195,6 +201,25 @@ enum Opt {
         /// directory in the project's target directory
         #[structopt(short, long, parse(from_os_str))]
         out: Option<PathBuf>,
+        /// Vendor the dependencies into the source distribution, so that it can be built offline
+        #[structopt(long)]
+        vendor: bool,
+        /// Only print which files the source distribution would contain and why, without
+        /// building it
+        #[structopt(long)]
+        list: bool,
+    },
+    #[cfg(feature = "auditwheel")]
+    #[structopt(name = "audit")]
+    /// Checks the shared libraries and executables in an existing wheel for compliance with the
+    /// manylinux or musllinux policy declared in its WHEEL file
+    Audit {
+        /// The wheel to check
+        #[structopt(parse(from_os_str))]
+        wheel: PathBuf,
+        /// Print the report as json instead of the human readable form
+        #[structopt(long)]
+        json: bool,
     },
     /// Backend for the PEP 517 integration. Not for human consumption
     ///
@@ -265,6 +290,10 @@ fn pep517(subcommand: PEP517Command) -> Result<()> {
                 BridgeModel::Bindings(_) => {
                     vec![context.interpreter[0].get_tag(&context.manylinux)]
                 }
+                BridgeModel::BindingsAbi3(major, minor) => {
+                    let platform = context.target.get_platform_tag(&context.manylinux);
+                    vec![format!("cp{}{}-abi3-{}", major, minor, platform)]
+                }
                 BridgeModel::Bin | BridgeModel::Cffi => {
                     context.target.get_universal_tags(&context.manylinux).1
                 }
@@ -274,8 +303,17 @@ fn pep517(subcommand: PEP517Command) -> Result<()> {
             write_dist_info(&mut writer, &context.metadata21, &context.scripts, &tags)?;
             println!("{}", context.metadata21.get_dist_info_dir().display());
         }
-        PEP517Command::BuildWheel { build, strip } => {
-            let build_context = build.into_build_context(true, strip)?;
+        PEP517Command::BuildWheel { mut build, strip } => {
+            // A source distribution with vendored dependencies must build without network access
+            if is_vendored(env::current_dir()?) {
+                build
+                    .cargo_extra_args
+                    .push("--offline --locked".to_string());
+            }
+            let mut build_context = build.into_build_context(true, strip)?;
+            // pip can only install a single wheel, so we build the crate without a variant
+            // unless one was selected
+            build_context.variants.clear();
             let wheels = build_context.build_wheels()?;
             assert_eq!(wheels.len(), 1);
             println!("{}", wheels[0].0.file_name().unwrap().to_str().unwrap());
@@ -288,8 +326,16 @@ fn pep517(subcommand: PEP517Command) -> Result<()> {
             let manifest_dir = manifest_path.parent().unwrap();
             let metadata21 = Metadata21::from_cargo_toml(&cargo_toml, &manifest_dir)
                 .context("Failed to parse Cargo.toml into python metadata")?;
-            let path = source_distribution(sdist_directory, &metadata21, &manifest_path, None)
-                .context("Failed to build source distribution")?;
+            let pyproject = get_pyproject_toml(manifest_dir)?;
+            let path = source_distribution(
+                sdist_directory,
+                &metadata21,
+                &manifest_path,
+                pyproject.sdist_include(),
+                pyproject.sdist_exclude(),
+                false,
+            )
+            .context("Failed to build source distribution")?;
             println!("{}", path.display());
         }
     };
@@ -309,7 +355,7 @@ fn upload_ui(build: BuildOptions, publish: &PublishOpt, no_sdist: bool) -> Resul
     let mut wheels = build_context.build_wheels()?;
 
     if !no_sdist {
-        if let Some(source_distribution) = build_context.build_source_distribution()? {
+        if let Some(source_distribution) = build_context.build_source_distribution(false)? {
             wheels.push(source_distribution);
         }
     }
@@ -407,10 +453,11 @@ fn run() -> Result<()> {
             release,
             strip,
             no_sdist,
+            vendor,
         } => {
             let build_context = build.into_build_context(release, strip)?;
             if !no_sdist {
-                build_context.build_source_distribution()?;
+                build_context.build_source_distribution(vendor)?;
             }
             build_context.build_wheels()?;
         }
@@ -458,7 +505,12 @@ fn run() -> Result<()> {
                 strip,
             )?;
         }
-        Opt::SDist { manifest_path, out } => {
+        Opt::SDist {
+            manifest_path,
+            out,
+            vendor,
+            list,
+        } => {
             let manifest_dir = manifest_path.parent().unwrap();
 
             // Ensure the project has a compliant pyproject.toml
@@ -469,6 +521,16 @@ fn run() -> Result<()> {
             let metadata21 = Metadata21::from_cargo_toml(&cargo_toml, &manifest_dir)
                 .context("Failed to parse Cargo.toml into python metadata")?;
 
+            if list {
+                return list_source_distribution(
+                    &metadata21,
+                    &manifest_path,
+                    pyproject.sdist_include(),
+                    pyproject.sdist_exclude(),
+                    vendor,
+                );
+            }
+
             let cargo_metadata = MetadataCommand::new()
                 .manifest_path(&manifest_path)
                 .exec()
@@ -487,9 +549,26 @@ fn run() -> Result<()> {
                 &metadata21,
                 &manifest_path,
                 pyproject.sdist_include(),
+                pyproject.sdist_exclude(),
+                vendor,
             )
             .context("Failed to build source distribution")?;
         }
+        #[cfg(feature = "auditwheel")]
+        Opt::Audit { wheel, json } => {
+            let audit = audit_wheel(&wheel)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&audit)?);
+            } else {
+                audit.print();
+            }
+            if !audit.is_compliant() {
+                bail!(
+                    "{} is not compliant with its platform tags",
+                    wheel.display()
+                );
+            }
+        }
         Opt::PEP517(subcommand) => pep517(subcommand)?,
     }
 
@@ -222,8 +222,10 @@ impl Metadata21 {
 
     /// Returns the version encoded according to PEP 427, Section "Escaping
     /// and Unicode"
+    ///
+    /// The `+` of a PEP 440 local version label is kept, as pip expects it
     pub fn get_version_escaped(&self) -> String {
-        let re = Regex::new(r"[^\w\d.]+").unwrap();
+        let re = Regex::new(r"[^\w\d.+]+").unwrap();
         re.replace_all(&self.version, "_").to_string()
     }
 
@@ -321,6 +323,14 @@ mod test {
         assert_eq!(
             metadata.get_dist_info_dir(),
             PathBuf::from("info_project-0.1.0.dist-info")
-        )
+        );
+
+        // The local version label of a feature variant is kept
+        let mut variant = metadata;
+        variant.version = "0.1.0+fast".to_string();
+        assert_eq!(
+            variant.get_dist_info_dir(),
+            PathBuf::from("info_project-0.1.0+fast.dist-info")
+        );
     }
 }
@@ -6,9 +6,11 @@ use crate::Target;
 use crate::{BridgeModel, Metadata21};
 use anyhow::{anyhow, bail, Context, Result};
 use flate2::write::GzEncoder;
-use flate2::Compression;
+use flate2::{Compression, GzBuilder};
+use regex::Regex;
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
 use std::ffi::OsStr;
 use std::fs;
 use std::fs::File;
@@ -139,12 +141,154 @@ impl ModuleWriter for PathWriter {
     }
 }
 
+/// The timestamp that is used when `SOURCE_DATE_EPOCH` isn't set: 1980-01-01, the earliest date
+/// a zip file can store
+const DEFAULT_SOURCE_DATE_EPOCH: u64 = 315_532_800;
+
+/// Returns the modification time for all files in wheels and source distributions, which is
+/// `SOURCE_DATE_EPOCH` if set (https://reproducible-builds.org/specs/source-date-epoch/), so
+/// that building the same sources twice results in the same archives
+pub(crate) fn source_date_epoch() -> Result<u64> {
+    match env::var("SOURCE_DATE_EPOCH") {
+        Ok(value) => {
+            let epoch = value.trim().parse::<u64>().context(format!(
+                "SOURCE_DATE_EPOCH must be a unix timestamp, but it is {:?}",
+                value
+            ))?;
+            // zip can't represent anything before 1980
+            Ok(epoch.max(DEFAULT_SOURCE_DATE_EPOCH))
+        }
+        Err(_) => Ok(DEFAULT_SOURCE_DATE_EPOCH),
+    }
+}
+
+/// Converts a unix timestamp into a zip timestamp, which is the UTC date and time with a
+/// precision of two seconds
+pub(crate) fn zip_date_time(epoch: u64) -> zip::DateTime {
+    // Converting days to a civil date, from http://howardhinnant.github.io/date_algorithms.html
+    let days = (epoch / 86400) as i64 + 719_468;
+    let seconds = epoch % 86400;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_part = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_part + 2) / 5 + 1;
+    let month = if month_part < 10 {
+        month_part + 3
+    } else {
+        month_part - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    zip::DateTime::from_date_and_time(
+        year as u16,
+        month as u8,
+        day as u8,
+        (seconds / 3600) as u8,
+        (seconds % 3600 / 60) as u8,
+        (seconds % 60) as u8,
+    )
+    // Dates after 2107 can't be stored
+    .unwrap_or_default()
+}
+
+/// Files are either executable (0o755) or not (0o644), independent of the umask and the
+/// permissions of the source files
+fn normalize_permissions(permissions: u32) -> u32 {
+    if permissions & 0o111 != 0 {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+/// Reads the permissions of a file, which are always 0o644 on windows
+fn file_permissions(source: &Path) -> Result<u32> {
+    #[cfg(not(target_os = "windows"))]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        Ok(fs::metadata(source)
+            .context(format!("Failed to read {}", source.display()))?
+            .permissions()
+            .mode())
+    }
+    #[cfg(target_os = "windows")]
+    {
+        fs::metadata(source).context(format!("Failed to read {}", source.display()))?;
+        Ok(0o644)
+    }
+}
+
+/// The files of an archive by their target, which are only written once the archive is
+/// finished so that they can be sorted by name.
+///
+/// Only the path to the contents is kept: Files from disk are read again when the archive is
+/// written and contents that were added as bytes are spooled to a temporary directory
+struct ArchiveFiles<T: Ord> {
+    /// The source of the contents and the normalized permissions, by target
+    files: BTreeMap<T, (PathBuf, u32)>,
+    spool: TempDir,
+    spooled: usize,
+}
+
+impl<T: Ord> ArchiveFiles<T> {
+    fn new() -> io::Result<Self> {
+        Ok(Self {
+            files: BTreeMap::new(),
+            spool: tempdir()?,
+            spooled: 0,
+        })
+    }
+
+    fn add_bytes(&mut self, target: T, bytes: &[u8], permissions: u32) -> io::Result<()> {
+        // A counter instead of the number of files, since a target can be added twice
+        let source = self.spool.path().join(self.spooled.to_string());
+        self.spooled += 1;
+        fs::write(&source, bytes)?;
+        self.add_file(target, source, permissions);
+        Ok(())
+    }
+
+    fn add_file(&mut self, target: T, source: PathBuf, permissions: u32) {
+        self.files
+            .insert(target, (source, normalize_permissions(permissions)));
+    }
+}
+
+/// Copies the file into the archive, returning the urlsafe base64 encoded sha256 of the contents
+/// and their size for the record file
+fn copy_hashed(source: &Path, writer: &mut impl Write) -> io::Result<(String, u64)> {
+    let mut file = File::open(source)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        writer.write_all(&buffer[..read])?;
+        size += read as u64;
+    }
+    let hash = base64::encode_config(&hasher.finalize(), base64::URL_SAFE_NO_PAD);
+    Ok((hash, size))
+}
+
 /// A glorified zip builder, mostly useful for writing the record file of a wheel
+///
+/// The files are collected first and written sorted by name, with the .dist-info directory
+/// last, when the wheel is finished, so that the same inputs always result in the same wheel
 pub struct WheelWriter {
     zip: ZipWriter<File>,
-    record: Vec<(String, String, usize)>,
+    files: ArchiveFiles<String>,
+    dist_info_dir: String,
     record_file: PathBuf,
     wheel_path: PathBuf,
+    elf_files: Vec<String>,
+    mtime: zip::DateTime,
 }
 
 impl ModuleWriter for WheelWriter {
@@ -161,21 +305,30 @@ impl ModuleWriter for WheelWriter {
         // The zip standard mandates using unix style paths
         let target = target.as_ref().to_str().unwrap().replace("\\", "/");
 
-        // Unlike users which can use the develop subcommand, the tests have to go through
-        // packing a zip which pip than has to unpack. This makes this 2-3 times faster
-        let compression_method = if cfg!(feature = "faster-tests") {
-            zip::CompressionMethod::Stored
-        } else {
-            zip::CompressionMethod::Deflated
-        };
-        let options = zip::write::FileOptions::default()
-            .unix_permissions(permissions)
-            .compression_method(compression_method);
-        self.zip.start_file(target.clone(), options)?;
-        self.zip.write_all(&bytes)?;
+        if bytes.starts_with(b"\x7fELF") {
+            self.elf_files.push(target.clone());
+        }
+
+        self.files.add_bytes(target, bytes, permissions)?;
+
+        Ok(())
+    }
+
+    fn add_file(&mut self, target: impl AsRef<Path>, source: impl AsRef<Path>) -> Result<()> {
+        let target = target.as_ref().to_str().unwrap().replace("\\", "/");
+        let source = source.as_ref();
+
+        let read_failed_context = format!("Failed to read {}", source.display());
+        let mut magic = Vec::new();
+        File::open(source)
+            .and_then(|file| file.take(4).read_to_end(&mut magic))
+            .context(read_failed_context)?;
+        if magic == b"\x7fELF" {
+            self.elf_files.push(target.clone());
+        }
 
-        let hash = base64::encode_config(&Sha256::digest(bytes), base64::URL_SAFE_NO_PAD);
-        self.record.push((target, hash, bytes.len()));
+        // Like for add_bytes, the permissions of the source don't matter
+        self.files.add_file(target, source.to_path_buf(), 0o644);
 
         Ok(())
     }
@@ -192,20 +345,18 @@ impl WheelWriter {
         scripts: &HashMap<String, String>,
         tags: &[String],
     ) -> Result<WheelWriter> {
-        let wheel_path = wheel_dir.join(format!(
-            "{}-{}-{}.whl",
-            metadata21.get_distribution_escaped(),
-            metadata21.get_version_escaped(),
-            tag
-        ));
+        let wheel_path = wheel_path(wheel_dir, metadata21, tag);
 
         let file = File::create(&wheel_path)?;
 
         let mut builder = WheelWriter {
             zip: ZipWriter::new(file),
-            record: Vec::new(),
+            files: ArchiveFiles::new()?,
+            dist_info_dir: metadata21.get_dist_info_dir().to_str().unwrap().to_string(),
             record_file: metadata21.get_dist_info_dir().join("RECORD"),
             wheel_path,
+            elf_files: Vec::new(),
+            mtime: zip_date_time(source_date_epoch()?),
         };
 
         write_dist_info(&mut builder, &metadata21, &scripts, &tags)?;
@@ -213,22 +364,53 @@ impl WheelWriter {
         Ok(builder)
     }
 
-    /// Creates the record file and finishes the zip
+    /// Returns the path in the wheel and the file with the contents of every elf file (shared
+    /// libraries and executables) that has been added so far, so they can be checked for
+    /// manylinux compliance
+    pub fn elf_files(&self) -> Vec<(&str, &Path)> {
+        self.elf_files
+            .iter()
+            .filter_map(|target| {
+                let (source, _) = self.files.files.get(target)?;
+                Some((target.as_str(), source.as_path()))
+            })
+            .collect()
+    }
+
+    /// Writes the files and the record file and finishes the zip
     pub fn finish(mut self) -> Result<PathBuf, io::Error> {
+        // Unlike users which can use the develop subcommand, the tests have to go through
+        // packing a zip which pip than has to unpack. This makes this 2-3 times faster
         let compression_method = if cfg!(feature = "faster-tests") {
             zip::CompressionMethod::Stored
         } else {
             zip::CompressionMethod::Deflated
         };
-        let options = zip::write::FileOptions::default().compression_method(compression_method);
-        let record_filename = self.record_file.to_str().unwrap().replace("\\", "/");
-        self.zip.start_file(&record_filename, options)?;
-        for (filename, hash, len) in self.record {
+        let options = zip::write::FileOptions::default()
+            .compression_method(compression_method)
+            .last_modified_time(self.mtime);
+
+        // The .dist-info directory should be at the end of the wheel (PEP 427)
+        let dist_info_prefix = format!("{}/", self.dist_info_dir.replace("\\", "/"));
+        let (dist_info, files): (Vec<_>, Vec<_>) = self
+            .files
+            .files
+            .iter()
+            .partition(|(target, _)| target.starts_with(&dist_info_prefix));
+
+        let mut record = String::new();
+        for (target, (source, permissions)) in files.into_iter().chain(dist_info) {
             self.zip
-                .write_all(format!("{},sha256={},{}\n", filename, hash, len).as_bytes())?;
+                .start_file(target.clone(), options.unix_permissions(*permissions))?;
+            let (hash, size) = copy_hashed(source, &mut self.zip)?;
+            record.push_str(&format!("{},sha256={},{}\n", target, hash, size));
         }
+
+        let record_filename = self.record_file.to_str().unwrap().replace("\\", "/");
+        record.push_str(&format!("{},,\n", record_filename));
         self.zip
-            .write_all(format!("{},,\n", record_filename).as_bytes())?;
+            .start_file(&record_filename, options.unix_permissions(0o644))?;
+        self.zip.write_all(record.as_bytes())?;
 
         self.zip.finish()?;
         Ok(self.wheel_path)
@@ -236,9 +418,18 @@ impl WheelWriter {
 }
 
 /// Creates a .tar.gz archive containing the source distribution
+///
+/// All files are placed in a top-level `{name}-{version}` directory, as the sdist format
+/// requires, so targets are relative to that directory.
+///
+/// Like for [WheelWriter], the files are written sorted by name when the archive is finished.
+/// Their mtime, owner and permissions are normalized and the gzip header contains no timestamp
 pub struct SDistWriter {
     tar: tar::Builder<GzEncoder<File>>,
+    files: ArchiveFiles<PathBuf>,
+    prefix: PathBuf,
     path: PathBuf,
+    mtime: u64,
 }
 
 impl ModuleWriter for SDistWriter {
@@ -252,28 +443,22 @@ impl ModuleWriter for SDistWriter {
         bytes: &[u8],
         permissions: u32,
     ) -> Result<()> {
-        let mut header = tar::Header::new_gnu();
-        header.set_size(bytes.len() as u64);
-        header.set_mode(permissions);
-        header.set_cksum();
-        self.tar
-            .append_data(&mut header, &target, bytes)
-            .context(format!(
-                "Failed to add {} bytes to sdist as {}",
-                bytes.len(),
-                target.as_ref().display()
-            ))?;
+        self.files
+            .add_bytes(target.as_ref().to_path_buf(), bytes, permissions)?;
         Ok(())
     }
 
     fn add_file(&mut self, target: impl AsRef<Path>, source: impl AsRef<Path>) -> Result<()> {
-        self.tar
-            .append_path_with_name(&source, &target)
-            .context(format!(
-                "Failed to add file from {} to sdist as {}",
-                source.as_ref().display(),
-                target.as_ref().display(),
-            ))?;
+        let permissions = file_permissions(source.as_ref()).context(format!(
+            "Failed to add file from {} to sdist as {}",
+            source.as_ref().display(),
+            target.as_ref().display(),
+        ))?;
+        self.files.add_file(
+            target.as_ref().to_path_buf(),
+            source.as_ref().to_path_buf(),
+            permissions,
+        );
         Ok(())
     }
 }
@@ -281,26 +466,72 @@ impl ModuleWriter for SDistWriter {
 impl SDistWriter {
     /// Create a source distribution .tar.gz which can be subsequently expanded
     pub fn new(wheel_dir: impl AsRef<Path>, metadata21: &Metadata21) -> Result<Self, io::Error> {
-        let path = wheel_dir.as_ref().join(format!(
-            "{}-{}.tar.gz",
-            &metadata21.get_distribution_escaped(),
-            &metadata21.get_version_escaped()
-        ));
+        let prefix = sdist_prefix(metadata21);
+        let path = sdist_path(wheel_dir.as_ref(), metadata21);
+
+        let mtime = source_date_epoch()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
 
         let tar_gz = File::create(&path)?;
-        let enc = GzEncoder::new(tar_gz, Compression::default());
+        // An mtime of 0 means that there is no timestamp in the gzip header
+        let enc = GzBuilder::new()
+            .mtime(0)
+            .operating_system(255)
+            .write(tar_gz, Compression::default());
         let tar = tar::Builder::new(enc);
 
-        Ok(Self { tar, path })
+        Ok(Self {
+            tar,
+            files: ArchiveFiles::new()?,
+            prefix,
+            path,
+            mtime,
+        })
     }
 
-    /// Finished the .tar.gz archive
+    /// Writes the files and finishes the .tar.gz archive
     pub fn finish(mut self) -> Result<PathBuf, io::Error> {
-        self.tar.finish()?;
+        for (target, (source, permissions)) in &self.files.files {
+            let file = File::open(source)?;
+            let mut header = tar::Header::new_gnu();
+            header.set_size(file.metadata()?.len());
+            header.set_mode(*permissions);
+            header.set_mtime(self.mtime);
+            header.set_uid(0);
+            header.set_gid(0);
+            header.set_cksum();
+            self.tar
+                .append_data(&mut header, self.prefix.join(target), file)?;
+        }
+        self.tar.into_inner()?.finish()?;
         Ok(self.path)
     }
 }
 
+/// Returns the `{name}-{version}` directory that contains the files of the source distribution
+pub(crate) fn sdist_prefix(metadata21: &Metadata21) -> PathBuf {
+    PathBuf::from(format!(
+        "{}-{}",
+        &metadata21.get_distribution_escaped(),
+        &metadata21.get_version_escaped()
+    ))
+}
+
+/// Returns the path of the source distribution in the wheel directory
+pub(crate) fn sdist_path(wheel_dir: &Path, metadata21: &Metadata21) -> PathBuf {
+    wheel_dir.join(format!("{}.tar.gz", sdist_prefix(metadata21).display()))
+}
+
+/// Returns the path of the wheel with the given tag in the wheel directory
+pub(crate) fn wheel_path(wheel_dir: &Path, metadata21: &Metadata21, tag: &str) -> PathBuf {
+    wheel_dir.join(format!(
+        "{}-{}-{}.whl",
+        metadata21.get_distribution_escaped(),
+        metadata21.get_version_escaped(),
+        tag
+    ))
+}
+
 fn wheel_file(tags: &[String]) -> String {
     let mut wheel_file = format!(
         "Wheel-Version: 1.0
@@ -312,12 +543,34 @@ Root-Is-Purelib: false
     );
 
     for tag in tags {
-        wheel_file += &format!("Tag: {}\n", tag);
+        for expanded in expand_compressed_tag(tag) {
+            wheel_file += &format!("Tag: {}\n", expanded);
+        }
     }
 
     wheel_file
 }
 
+/// Expands a compressed tag set such as `cp38-cp38-manylinux_2_17_x86_64.manylinux2014_x86_64`
+/// into the individual tags, since the WHEEL file must list each tag on its own line
+///
+/// https://www.python.org/dev/peps/pep-0425/#compressed-tag-sets
+pub(crate) fn expand_compressed_tag(tag: &str) -> Vec<String> {
+    let parts: Vec<&str> = tag.splitn(3, '-').collect();
+    if parts.len() != 3 {
+        return vec![tag.to_string()];
+    }
+    let mut expanded = Vec::new();
+    for python in parts[0].split('.') {
+        for abi in parts[1].split('.') {
+            for platform in parts[2].split('.') {
+                expanded.push(format!("{}-{}-{}", python, abi, platform));
+            }
+        }
+    }
+    expanded
+}
+
 /// https://packaging.python.org/specifications/entry-points/
 fn entry_points_txt(entrypoints: &HashMap<String, String, impl std::hash::BuildHasher>) -> String {
     entrypoints
@@ -389,6 +642,35 @@ fn cffi_header(crate_dir: &Path, tempdir: &TempDir) -> Result<PathBuf> {
     }
 }
 
+/// Extracts the names of the functions and `extern` variables declared in a C header
+fn header_declarations(header: &str) -> Vec<String> {
+    let comments = Regex::new(r"(?s)/\*.*?\*/|//[^\n]*").unwrap();
+    // A name followed by a parenthesis, unless it's `(*`, which is the return type of a function
+    // pointer such as `void` in `typedef void (*Callback)(int32_t value);`
+    let functions = Regex::new(r"\b([A-Za-z_]\w*)\s*\(\s*[^*\s]").unwrap();
+    let variables = Regex::new(r"\bextern\b[^;(]*?\b([A-Za-z_]\w*)\s*(\[[^\]]*\])?\s*;").unwrap();
+
+    let header = comments.replace_all(header, "");
+    let mut declarations: Vec<String> = functions
+        .captures_iter(&header)
+        .chain(variables.captures_iter(&header))
+        .map(|captures| captures[1].to_string())
+        .collect();
+    declarations.sort();
+    declarations.dedup();
+    declarations
+}
+
+/// Returns the functions and variables declared in the header used for cffi, i.e. the symbols
+/// the native library is expected to export
+pub(crate) fn cffi_header_symbols(crate_dir: &Path) -> Result<Vec<String>> {
+    let tempdir = tempdir()?;
+    let header = cffi_header(crate_dir, &tempdir)?;
+    let header = fs::read_to_string(&header)
+        .context(format!("Failed to read the header at {}", header.display()))?;
+    Ok(header_declarations(&header))
+}
+
 /// Returns the content of what will become ffi.py by invoking cbindgen and cffi
 ///
 /// Checks if user has provided their own header at `target/header.h`, otherwise
@@ -503,15 +785,28 @@ fn handle_cffi_call_result(
 }
 
 /// Copies the shared library into the module, which is the only extra file needed with bindings
+///
+/// Without a python interpreter, the library gets the name of an abi3 module, e.g. `foo.abi3.so`
 pub fn write_bindings_module(
     writer: &mut impl ModuleWriter,
     project_layout: &ProjectLayout,
     module_name: &str,
     artifact: &Path,
-    python_interpreter: &PythonInterpreter,
+    python_interpreter: Option<&PythonInterpreter>,
+    target: &Target,
     develop: bool,
 ) -> Result<()> {
-    let so_filename = python_interpreter.get_library_name(&module_name);
+    let so_filename = match python_interpreter {
+        Some(python_interpreter) => python_interpreter.get_library_name(&module_name),
+        // abi3 modules don't have a version specific suffix
+        None => {
+            if target.is_unix() {
+                format!("{}.abi3.so", module_name)
+            } else {
+                format!("{}.pyd", module_name)
+            }
+        }
+    };
 
     match project_layout {
         ProjectLayout::Mixed(ref python_module) => {
@@ -669,3 +964,154 @@ pub fn write_dist_info(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::CargoToml;
+
+    #[test]
+    fn test_wheel_file_expands_compressed_tags() {
+        let tags = vec![
+            "cp38-cp38-manylinux_2_17_x86_64.manylinux2014_x86_64".to_string(),
+            "py3-none-win_amd64".to_string(),
+        ];
+        let wheel_file = wheel_file(&tags);
+        let tag_lines: Vec<&str> = wheel_file
+            .lines()
+            .filter(|line| line.starts_with("Tag: "))
+            .collect();
+        assert_eq!(
+            tag_lines,
+            vec![
+                "Tag: cp38-cp38-manylinux_2_17_x86_64",
+                "Tag: cp38-cp38-manylinux2014_x86_64",
+                "Tag: py3-none-win_amd64",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_bindings_module_abi3() {
+        let tempdir = tempdir().unwrap();
+        let artifact = tempdir.path().join("libfoo.so");
+        fs::write(&artifact, b"").unwrap();
+        let module_dir = tempdir.path().join("module");
+        fs::create_dir(&module_dir).unwrap();
+        let mut writer = PathWriter::from_path(&module_dir);
+        let target =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        write_bindings_module(
+            &mut writer,
+            &ProjectLayout::PureRust,
+            "foo",
+            &artifact,
+            None,
+            &target,
+            false,
+        )
+        .unwrap();
+        assert!(module_dir.join("foo.abi3.so").is_file());
+    }
+
+    #[test]
+    fn test_zip_date_time() {
+        let date_time = zip_date_time(DEFAULT_SOURCE_DATE_EPOCH);
+        assert_eq!(
+            (date_time.year(), date_time.month(), date_time.day()),
+            (1980, 1, 1)
+        );
+        // 2020-02-29 13:37:42
+        let date_time = zip_date_time(1_582_983_462);
+        assert_eq!(
+            (date_time.year(), date_time.month(), date_time.day()),
+            (2020, 2, 29)
+        );
+        assert_eq!(
+            (date_time.hour(), date_time.minute(), date_time.second()),
+            (13, 37, 42)
+        );
+    }
+
+    #[test]
+    fn test_writers_are_reproducible() {
+        let cargo_toml =
+            CargoToml::from_path(Path::new("test-crates/hello-world/Cargo.toml")).unwrap();
+        let metadata21 =
+            Metadata21::from_cargo_toml(&cargo_toml, Path::new("test-crates/hello-world")).unwrap();
+        let tags = vec!["py3-none-any".to_string()];
+        let sources = tempdir().unwrap();
+        let source = sources.path().join("data.txt");
+        fs::write(&source, "data").unwrap();
+
+        let build = |files: &[(&str, &[u8], u32)]| {
+            let tempdir = tempdir().unwrap();
+            let mut wheel = WheelWriter::new(
+                "py3-none-any",
+                tempdir.path(),
+                &metadata21,
+                &HashMap::new(),
+                &tags,
+            )
+            .unwrap();
+            let mut sdist = SDistWriter::new(tempdir.path(), &metadata21).unwrap();
+            wheel.add_file("c/data.txt", &source).unwrap();
+            sdist.add_file("c/data.txt", &source).unwrap();
+            for (target, bytes, permissions) in files {
+                wheel
+                    .add_bytes_with_permissions(target, bytes, *permissions)
+                    .unwrap();
+                sdist
+                    .add_bytes_with_permissions(target, bytes, *permissions)
+                    .unwrap();
+            }
+            let wheel = fs::read(wheel.finish().unwrap()).unwrap();
+            let sdist = fs::read(sdist.finish().unwrap()).unwrap();
+            (wheel, sdist)
+        };
+
+        // Neither the order of the files nor the umask may change the archives
+        let first = build(&[("b.py", b"b", 0o600), ("a/bin", b"\x7fELF", 0o700)]);
+        let second = build(&[("a/bin", b"\x7fELF", 0o755), ("b.py", b"b", 0o644)]);
+        assert!(first == second);
+    }
+
+    #[test]
+    fn test_header_declarations() {
+        let header = r#"
+typedef struct {
+  double x;
+  double y;
+} Point;
+
+typedef void (*Callback)(int32_t value);
+
+extern const int32_t ANSWER;
+
+extern uint8_t BUFFER[16];
+
+/**
+ * Not a call: ignored(1)
+ */
+Point get_origin(void);
+
+bool is_in_range(Point point,
+                 double range);
+
+void set_callback(void (*callback)(int32_t value));
+
+int32_t (*get_callback(void))(int32_t value);
+"#;
+        assert_eq!(
+            header_declarations(header),
+            vec![
+                "ANSWER",
+                "BUFFER",
+                "get_callback",
+                "get_origin",
+                "is_in_range",
+                "set_callback"
+            ]
+        );
+    }
+}
@@ -3,8 +3,11 @@ use crate::{BridgeModel, Manylinux};
 use anyhow::{bail, format_err, Context, Result};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::OsString;
 use std::fmt;
+use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
@@ -290,6 +293,41 @@ pub struct PythonInterpreter {
     ///
     /// Note that this always `None` on windows
     pub abi_tag: Option<String>,
+    /// The sysconfig this interpreter was read from instead of running it, e.g. for cross
+    /// compiling. In that case `executable` is the path of the sysconfig.
+    pub sysconfig: Option<PathBuf>,
+}
+
+/// Whether the sysconfig is a `_sysconfigdata*.py` module as opposed to the output of
+/// `python -m sysconfig`
+fn is_sysconfigdata(sysconfig: &Path) -> bool {
+    sysconfig.file_name().map_or(false, |name| {
+        let name = name.to_string_lossy();
+        name.starts_with("_sysconfigdata") && name.ends_with(".py")
+    })
+}
+
+/// pyo3 only switches to cross compiling when PYO3_CROSS_LIB_DIR is set, which then has to
+/// contain the `_sysconfigdata` module. For the output of `python -m sysconfig`, there is no such
+/// module, so the user has to point PYO3_CROSS_LIB_DIR to the python libraries themselves.
+fn check_pyo3_cross(
+    sysconfig: &Path,
+    bridge: &BridgeModel,
+    cross_lib_dir: Option<OsString>,
+) -> Result<()> {
+    let is_bindings = match bridge {
+        BridgeModel::Bindings(_) | BridgeModel::BindingsAbi3(_, _) => true,
+        BridgeModel::Cffi | BridgeModel::Bin => false,
+    };
+    if is_bindings && !is_sysconfigdata(sysconfig) && cross_lib_dir.is_none() {
+        bail!(
+            "{} looks like the output of `python -m sysconfig`, which pyo3 can't read. \
+             Please pass the `_sysconfigdata*.py` module of the interpreter instead or \
+             set PYO3_CROSS_LIB_DIR",
+            sysconfig.display()
+        );
+    }
+    Ok(())
 }
 
 /// Returns the abiflags that are assembled through the message, with some
@@ -352,6 +390,111 @@ fn fun_with_abiflags(
     }
 }
 
+/// Reads the variables from either the output of `python -m sysconfig` or from a
+/// `_sysconfigdata` module, so that we get the same information as with
+/// [GET_INTERPRETER_METADATA] without running the interpreter
+///
+/// The `Platform` and `Python version` lines of `python -m sysconfig` are also included
+fn parse_sysconfig(contents: &str) -> HashMap<String, String> {
+    let mut variables = HashMap::new();
+    if contents.contains("build_time_vars") {
+        // e.g. `'SOABI': 'cpython-38-aarch64-linux-gnu',` or `'Py_DEBUG': 0,`. Long strings are
+        // split into adjacent literals over multiple lines by pprint
+        let entry =
+            Regex::new(r#"'(\w+)':\s*((?:(?:'(?:[^'\\]|\\.)*'|"(?:[^"\\]|\\.)*")\s*)+|-?\d+)"#)
+                .unwrap();
+        let literal = Regex::new(r#"'((?:[^'\\]|\\.)*)'|"((?:[^"\\]|\\.)*)""#).unwrap();
+        for capture in entry.captures_iter(contents) {
+            let value = &capture[2];
+            let value = if value.starts_with('\'') || value.starts_with('"') {
+                literal
+                    .captures_iter(value)
+                    .map(|part| part.get(1).or_else(|| part.get(2)).unwrap().as_str())
+                    .collect::<String>()
+            } else {
+                value.to_string()
+            };
+            variables.insert(capture[1].to_string(), value);
+        }
+    } else {
+        // e.g. `Platform: "linux-aarch64"` or `    SOABI = "cpython-38-aarch64-linux-gnu"`
+        let header = Regex::new(r#"^(Platform|Python version): "(.*)"\s*$"#).unwrap();
+        let variable = Regex::new(r#"^\s+(\w+) = "(.*)"\s*$"#).unwrap();
+        for line in contents.lines() {
+            if let Some(capture) = header.captures(line).or_else(|| variable.captures(line)) {
+                variables.insert(capture[1].to_string(), capture[2].to_string());
+            }
+        }
+    }
+    variables
+}
+
+/// Assembles the same message [GET_INTERPRETER_METADATA] would print from the sysconfig
+/// variables of the interpreter
+fn sysconfig_metadata(
+    variables: &HashMap<String, String>,
+    target: &Target,
+) -> Result<IntepreterMetadataMessage> {
+    // `Python version` is e.g. "3.8", while VERSION is "37" on windows
+    let version = variables
+        .get("Python version")
+        .or_else(|| variables.get("VERSION"))
+        .ok_or_else(|| format_err!("The sysconfig doesn't contain the python version"))?;
+    let (major, minor) = if version.contains('.') {
+        let mut parts = version.splitn(2, '.');
+        (parts.next().unwrap(), parts.next().unwrap())
+    } else if !version.is_empty() {
+        version.split_at(1)
+    } else {
+        bail!("The sysconfig contains an empty python version");
+    };
+    let context = format!("Invalid python version {} in the sysconfig", version);
+    let major = major.parse::<usize>().context(context.clone())?;
+    let minor = minor.parse::<usize>().context(context)?;
+
+    let soabi = variables.get("SOABI");
+    let interpreter = match soabi {
+        Some(soabi) if soabi.starts_with("pypy") => "pypy",
+        _ => "cpython",
+    };
+
+    // The sysconfig of the `_sysconfigdata` module doesn't contain the platform, so we trust
+    // the target in that case
+    let platform = match variables
+        .get("Platform")
+        .or_else(|| variables.get("MACHDEP"))
+        .map(|platform| platform.split('-').next().unwrap())
+    {
+        Some("win") | Some("win32") => "windows".to_string(),
+        Some("macosx") => "darwin".to_string(),
+        Some(platform) if platform.starts_with("freebsd") => "freebsd".to_string(),
+        Some(platform) if platform.starts_with("linux") => "linux".to_string(),
+        Some(platform) => platform.to_string(),
+        None if target.is_windows() => "windows".to_string(),
+        None if target.is_macos() => "darwin".to_string(),
+        None if target.is_freebsd() => "freebsd".to_string(),
+        None => "linux".to_string(),
+    };
+
+    let flag = |name: &str, value: &str| variables.get(name).map(String::as_str) == Some(value);
+
+    Ok(IntepreterMetadataMessage {
+        major,
+        minor,
+        abiflags: variables.get("ABIFLAGS").cloned(),
+        interpreter: interpreter.to_string(),
+        ext_suffix: variables.get("EXT_SUFFIX").cloned(),
+        m: flag("WITH_PYMALLOC", "1"),
+        u: flag("Py_UNICODE_SIZE", "4"),
+        d: flag("Py_DEBUG", "1"),
+        platform,
+        abi_tag: soabi
+            .and_then(|soabi| soabi.split('-').nth(1))
+            .filter(|abi_tag| !abi_tag.is_empty())
+            .map(ToString::to_string),
+    })
+}
+
 impl PythonInterpreter {
     /// Returns the supported python environment in the PEP 425 format:
     /// {python tag}-{abi tag}-{platform tag}
@@ -427,7 +570,9 @@ impl PythonInterpreter {
     pub fn get_library_name(&self, base: &str) -> String {
         match self.interpreter {
             Interpreter::CPython => {
-                let platform = self.target.get_shared_platform_tag();
+                let platform = self
+                    .target
+                    .get_shared_platform_tag((self.major, self.minor));
 
                 if self.target.is_freebsd() {
                     format!(
@@ -530,9 +675,83 @@ impl PythonInterpreter {
             ext_suffix: message.ext_suffix,
             interpreter,
             abi_tag: message.abi_tag,
+            sysconfig: None,
         }))
     }
 
+    /// Creates a [PythonInterpreter] from the output of `python -m sysconfig` or from a
+    /// `_sysconfigdata` module instead of running the interpreter, which e.g. isn't possible
+    /// when cross compiling
+    pub fn from_sysconfig(
+        sysconfig: &Path,
+        target: &Target,
+        bridge: &BridgeModel,
+    ) -> Result<PythonInterpreter> {
+        let contents = fs::read_to_string(sysconfig).context(format!(
+            "Failed to read the sysconfig {}",
+            sysconfig.display()
+        ))?;
+        let err_msg = format!(
+            "Failed to get information from the sysconfig at {}",
+            sysconfig.display()
+        );
+        let message =
+            sysconfig_metadata(&parse_sysconfig(&contents), &target).context(err_msg.clone())?;
+        check_pyo3_cross(sysconfig, bridge, env::var_os("PYO3_CROSS_LIB_DIR"))?;
+
+        let interpreter = match message.interpreter.as_str() {
+            "pypy" => Interpreter::PyPy,
+            _ => Interpreter::CPython,
+        };
+
+        let abiflags = fun_with_abiflags(&message, &target, &bridge).context(err_msg)?;
+
+        Ok(PythonInterpreter {
+            major: message.major,
+            minor: message.minor,
+            abiflags,
+            target: target.clone(),
+            executable: sysconfig.to_path_buf(),
+            ext_suffix: message.ext_suffix,
+            interpreter,
+            abi_tag: message.abi_tag,
+            sysconfig: Some(sysconfig.to_path_buf()),
+        })
+    }
+
+    /// The environment variables that make pyo3's build script use this interpreter's
+    /// configuration without running it. Only interpreters read from a sysconfig have those.
+    ///
+    /// For a `_sysconfigdata` module, PYO3_CROSS_LIB_DIR is the directory of the module. For the
+    /// output of `python -m sysconfig`, the user has to set PYO3_CROSS_LIB_DIR
+    pub fn pyo3_cross_env(&self) -> Vec<(&'static str, String)> {
+        let sysconfig = match self.sysconfig {
+            Some(ref sysconfig) => sysconfig,
+            None => return Vec::new(),
+        };
+        let mut env = vec![(
+            "PYO3_CROSS_PYTHON_VERSION",
+            format!("{}.{}", self.major, self.minor),
+        )];
+        if is_sysconfigdata(sysconfig) {
+            let lib_dir = sysconfig.parent().unwrap_or_else(|| Path::new(""));
+            env.push(("PYO3_CROSS_LIB_DIR", lib_dir.display().to_string()));
+        }
+        env
+    }
+
+    /// Reads all the given sysconfigs, see [PythonInterpreter::from_sysconfig]
+    pub fn from_sysconfigs(
+        sysconfigs: &[PathBuf],
+        target: &Target,
+        bridge: &BridgeModel,
+    ) -> Result<Vec<PythonInterpreter>> {
+        sysconfigs
+            .iter()
+            .map(|sysconfig| PythonInterpreter::from_sysconfig(sysconfig, target, bridge))
+            .collect()
+    }
+
     /// Tries to find all installed python versions using the heuristic for the
     /// given platform
     pub fn find_all(target: &Target, bridge: &BridgeModel) -> Result<Vec<PythonInterpreter>> {
@@ -594,3 +813,126 @@ impl fmt::Display for PythonInterpreter {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sysconfig_path(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("sysconfig")
+            .join(name)
+    }
+
+    #[test]
+    fn test_from_sysconfig_dump() {
+        let target =
+            Target::from_target_triple(Some("aarch64-unknown-linux-gnu".to_string())).unwrap();
+        let sysconfig = sysconfig_path("cpython-linux-3.8-aarch64.txt");
+        // pyo3 can't read the output of `python -m sysconfig`
+        let pyo3 = BridgeModel::Bindings("pyo3".to_string());
+        assert!(check_pyo3_cross(&sysconfig, &pyo3, None).is_err());
+        check_pyo3_cross(&sysconfig, &pyo3, Some("/sysroot/usr/lib".into())).unwrap();
+        check_pyo3_cross(&sysconfig, &BridgeModel::Bin, None).unwrap();
+
+        let interpreter =
+            PythonInterpreter::from_sysconfig(&sysconfig, &target, &BridgeModel::Bin).unwrap();
+        assert_eq!((interpreter.major, interpreter.minor), (3, 8));
+        assert_eq!(interpreter.interpreter, Interpreter::CPython);
+        assert_eq!(interpreter.abiflags, "");
+        assert_eq!(interpreter.abi_tag, Some("38".to_string()));
+        assert_eq!(
+            interpreter.get_library_name("foo"),
+            "foo.cpython-38-aarch64-linux-gnu.so"
+        );
+        // CPython before 3.13 uses the glibc suffix on musl too
+        let musl = PythonInterpreter::from_sysconfig(
+            &sysconfig,
+            &Target::from_target_triple(Some("aarch64-unknown-linux-musl".to_string())).unwrap(),
+            &BridgeModel::Bin,
+        )
+        .unwrap();
+        assert_eq!(
+            musl.get_library_name("foo"),
+            "foo.cpython-38-aarch64-linux-gnu.so"
+        );
+        // The user has to set PYO3_CROSS_LIB_DIR for a text dump
+        assert_eq!(
+            interpreter.pyo3_cross_env(),
+            vec![("PYO3_CROSS_PYTHON_VERSION", "3.8".to_string())]
+        );
+
+        let pypy = PythonInterpreter::from_sysconfig(
+            &sysconfig_path("pypy-linux-3.6-7.0.txt"),
+            &Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap(),
+            &BridgeModel::Bin,
+        )
+        .unwrap();
+        assert_eq!(pypy.interpreter, Interpreter::PyPy);
+        assert_eq!(
+            pypy.get_library_name("foo"),
+            "foo.pypy3-70-x86_64-linux-gnu.so"
+        );
+
+        // The sysconfig doesn't match the target
+        assert!(PythonInterpreter::from_sysconfig(
+            &sysconfig_path("cpython-win-3.7.txt"),
+            &target,
+            &BridgeModel::Bin,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_from_sysconfigdata() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let python_dir = tempdir.path().join("lib").join("python3.7");
+        fs::create_dir_all(&python_dir).unwrap();
+        let sysconfigdata = python_dir.join("_sysconfigdata_m_linux_arm-linux-gnueabihf.py");
+        fs::write(
+            &sysconfigdata,
+            r#"# system configuration generated and used by the sysconfig module
+build_time_vars = {'ABIFLAGS': 'm',
+ 'CFLAGS': '-Wno-unused-result -Wsign-compare -DNDEBUG -g -fwrapv -O3 '
+           '-Wall',
+ 'EXT_SUFFIX': '.cpython-37m-arm-linux-gnueabihf.so',
+ 'MACHDEP': 'linux',
+ 'MULTIARCH_CPPFLAGS': "-DMULTIARCH='arm-linux-gnueabihf'",
+ 'Py_DEBUG': 0,
+ 'SOABI': 'cpython-37m-arm-linux-gnueabihf',
+ 'VERSION': '3.7',
+ 'WITH_PYMALLOC': 1}
+"#,
+        )
+        .unwrap();
+
+        let variables = parse_sysconfig(&fs::read_to_string(&sysconfigdata).unwrap());
+        assert_eq!(
+            variables["CFLAGS"],
+            "-Wno-unused-result -Wsign-compare -DNDEBUG -g -fwrapv -O3 -Wall"
+        );
+        assert_eq!(
+            variables["MULTIARCH_CPPFLAGS"],
+            "-DMULTIARCH='arm-linux-gnueabihf'"
+        );
+        assert_eq!(variables["WITH_PYMALLOC"], "1");
+
+        let target =
+            Target::from_target_triple(Some("armv7-unknown-linux-gnueabihf".to_string())).unwrap();
+        let interpreter = PythonInterpreter::from_sysconfig(
+            &sysconfigdata,
+            &target,
+            &BridgeModel::Bindings("pyo3".to_string()),
+        )
+        .unwrap();
+        assert_eq!((interpreter.major, interpreter.minor), (3, 7));
+        assert_eq!(interpreter.abiflags, "m");
+        assert_eq!(
+            interpreter.pyo3_cross_env(),
+            vec![
+                ("PYO3_CROSS_PYTHON_VERSION", "3.7".to_string()),
+                ("PYO3_CROSS_LIB_DIR", python_dir.display().to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,401 @@
+//! Vendors the shared libraries that violate the manylinux policy into the wheel, the equivalent
+//! of `auditwheel repair`
+//!
+//! The libraries are copied into a `<module>.libs` directory with a hash suffix in their name
+//! (so they can't clash with libraries of other packages), their SONAME is rewritten to match and
+//! the native module is patched to load them through its RUNPATH. The elf patching is done with
+//! [patchelf](https://github.com/NixOS/patchelf), which must be installed.
+
+use crate::auditwheel::{check_symbol_versions, find_external_libraries};
+use crate::module_writer::ModuleWriter;
+use crate::Manylinux;
+use crate::Target;
+use anyhow::{bail, format_err, Context, Result};
+use goblin::elf::dynamic::{DT_RPATH, DT_RUNPATH};
+use goblin::elf::Elf;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str;
+
+/// Returns the directory in the wheel the external libraries are copied to, i.e. `<module>.libs`
+pub fn libs_dir(module_name: &str) -> PathBuf {
+    PathBuf::from(format!("{}.libs", module_name))
+}
+
+/// Runs patchelf with the given arguments on the elf file
+fn patchelf(args: &[&OsStr], file: &Path) -> Result<()> {
+    let output = Command::new("patchelf")
+        .args(args)
+        .arg(file)
+        .output()
+        .context("Failed to run patchelf, which is required to repair wheels. Is it installed?")?;
+    if !output.status.success() {
+        bail!(
+            "patchelf failed to patch {}: {}\n--- Stdout:\n{}\n--- Stderr:\n{}",
+            file.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr),
+        );
+    }
+    Ok(())
+}
+
+/// Returns the elf machine type and the directories from DT_RPATH and DT_RUNPATH with `$ORIGIN`
+/// expanded
+fn read_elf_info(path: &Path) -> Result<(u16, Vec<PathBuf>)> {
+    let buffer = fs::read(path).context(format!("Failed to read {}", path.display()))?;
+    let elf = Elf::parse(&buffer).context(format!("Failed to parse {}", path.display()))?;
+    let origin = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut rpaths = Vec::new();
+    if let Some(ref dynamic) = elf.dynamic {
+        for dyn_ in &dynamic.dyns {
+            if dyn_.d_tag != DT_RPATH && dyn_.d_tag != DT_RUNPATH {
+                continue;
+            }
+            if let Some(Ok(value)) = elf.dynstrtab.get(dyn_.d_val as usize) {
+                for dir in value.split(':').filter(|dir| !dir.is_empty()) {
+                    let dir = dir
+                        .replace("$ORIGIN", &origin.display().to_string())
+                        .replace("${ORIGIN}", &origin.display().to_string());
+                    rpaths.push(PathBuf::from(dir));
+                }
+            }
+        }
+    }
+
+    Ok((elf.header.e_machine, rpaths))
+}
+
+/// Returns the libraries known to the dynamic linker cache, as listed by `ldconfig -p`
+fn ldconfig_cache() -> Vec<(String, PathBuf)> {
+    let output = match Command::new("ldconfig").arg("-p").output() {
+        Ok(output) if output.status.success() => output,
+        // Not having ldconfig (or not having it in PATH) is fine, we still have the default dirs
+        _ => match Command::new("/sbin/ldconfig").arg("-p").output() {
+            Ok(output) if output.status.success() => output,
+            _ => return Vec::new(),
+        },
+    };
+
+    // The lines look like `	libssl.so.1.1 (libc6,x86-64) => /usr/lib/x86_64-linux-gnu/libssl.so.1.1`
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.trim().splitn(2, " => ");
+            let name = parts.next()?.split_whitespace().next()?;
+            let path = parts.next()?;
+            Some((name.to_string(), PathBuf::from(path)))
+        })
+        .collect()
+}
+
+/// Searches the library the same way the dynamic linker would: The RPATH/RUNPATH of the
+/// library depending on it, `LD_LIBRARY_PATH`, the ldconfig cache and then the default
+/// directories. Only libraries for the same machine type are considered.
+fn find_library(
+    name: &str,
+    rpaths: &[PathBuf],
+    ldconfig_cache: &[(String, PathBuf)],
+    machine: u16,
+) -> Result<PathBuf> {
+    let mut candidates: Vec<PathBuf> = rpaths.iter().map(|dir| dir.join(name)).collect();
+    if let Some(ld_library_path) = env::var_os("LD_LIBRARY_PATH") {
+        candidates.extend(env::split_paths(&ld_library_path).map(|dir| dir.join(name)));
+    }
+    candidates.extend(
+        ldconfig_cache
+            .iter()
+            .filter(|(cached, _)| cached == name)
+            .map(|(_, path)| path.clone()),
+    );
+    for dir in &["/lib64", "/usr/lib64", "/lib", "/usr/lib"] {
+        candidates.push(Path::new(dir).join(name));
+    }
+
+    for candidate in candidates {
+        if !candidate.is_file() {
+            continue;
+        }
+        match read_elf_info(&candidate) {
+            Ok((candidate_machine, _)) if candidate_machine == machine => return Ok(candidate),
+            _ => continue,
+        }
+    }
+
+    Err(format_err!(
+        "Couldn't find {} on the build host. \
+         Make sure it is installed or add its directory to LD_LIBRARY_PATH",
+        name
+    ))
+}
+
+/// Inserts the first 8 hex digits of the sha256 of the file into the library name before the
+/// last `.so` component, e.g. `libfoo.so.1` becomes `libfoo-1a2b3c4d.so.1` and
+/// `libfoo.socket.so.1` becomes `libfoo.socket-1a2b3c4d.so.1`
+fn hashed_name(name: &str, path: &Path) -> Result<String> {
+    let contents = fs::read(path).context(format!("Failed to read {}", path.display()))?;
+    let hash = format!("{:x}", Sha256::digest(&contents));
+    let short_hash = &hash[..8];
+    // `.so` is only a component if it is followed by the end of the name or a version
+    let so_component = name
+        .match_indices(".so")
+        .map(|(pos, _)| pos)
+        .filter(|pos| {
+            let rest = &name[pos + 3..];
+            rest.is_empty() || rest.starts_with('.')
+        })
+        .last();
+    Ok(match so_component {
+        Some(pos) => format!("{}-{}{}", &name[..pos], short_hash, &name[pos..]),
+        None => format!("{}-{}", name, short_hash),
+    })
+}
+
+/// Finds the libraries the artifact links to but which are not allowed by the manylinux policy
+/// and their own external dependencies on the build host, which are the libraries that
+/// [repair_artifact] vendors. Returns the original name and the location of each library.
+pub fn find_vendored_libraries(
+    artifact: &Path,
+    target: &Target,
+    manylinux: &Manylinux,
+) -> Result<BTreeMap<String, PathBuf>> {
+    let mut resolved: BTreeMap<String, PathBuf> = BTreeMap::new();
+    let external = find_external_libraries(artifact, target, manylinux)?;
+    if external.is_empty() {
+        return Ok(resolved);
+    }
+
+    let (machine, rpaths) = read_elf_info(artifact)?;
+    let ldconfig_cache = ldconfig_cache();
+    let mut queue: Vec<(String, Vec<PathBuf>)> = external
+        .into_iter()
+        .map(|name| (name, rpaths.clone()))
+        .collect();
+    while let Some((name, rpaths)) = queue.pop() {
+        if resolved.contains_key(&name) {
+            continue;
+        }
+        let path = find_library(&name, &rpaths, &ldconfig_cache, machine)?;
+        let (_, dep_rpaths) = read_elf_info(&path)?;
+        for dep in find_external_libraries(&path, target, manylinux)? {
+            queue.push((dep, dep_rpaths.clone()));
+        }
+        resolved.insert(name, path);
+    }
+    Ok(resolved)
+}
+
+/// Copies the libraries the artifact links to but which are not allowed by the manylinux policy
+/// (and their own external dependencies) from the build host into `<module>.libs` in the wheel.
+///
+/// `artifact_dir` is the directory of the artifact relative to the root of the wheel, which is
+/// used to set a RUNPATH relative to `$ORIGIN`. The patched copy of the artifact is placed in
+/// `tempdir` and its path returned, so it can be added to the wheel instead of the original.
+pub fn repair_artifact(
+    writer: &mut impl ModuleWriter,
+    artifact: &Path,
+    artifact_dir: &Path,
+    module_name: &str,
+    target: &Target,
+    manylinux: &Manylinux,
+    tempdir: &Path,
+) -> Result<PathBuf> {
+    let resolved = find_vendored_libraries(artifact, target, manylinux)?;
+    if resolved.is_empty() {
+        return Ok(artifact.to_path_buf());
+    }
+
+    let mut renamed = BTreeMap::new();
+    for (name, path) in &resolved {
+        renamed.insert(name.clone(), hashed_name(name, path)?);
+    }
+
+    let replace_needed = |file: &Path| -> Result<()> {
+        let buffer = fs::read(file)?;
+        let elf = Elf::parse(&buffer)?;
+        let needed: Vec<String> = elf.libraries.iter().map(ToString::to_string).collect();
+        for dep in needed {
+            if let Some(new_name) = renamed.get(&dep) {
+                patchelf(
+                    &[
+                        OsStr::new("--replace-needed"),
+                        OsStr::new(&dep),
+                        OsStr::new(new_name),
+                    ],
+                    file,
+                )?;
+            }
+        }
+        Ok(())
+    };
+
+    let libs_dir = libs_dir(module_name);
+    println!(
+        "🖨  Copying external shared libraries to {}:",
+        libs_dir.display()
+    );
+    for (name, path) in &resolved {
+        let new_name = &renamed[name];
+        let dest = tempdir.join(new_name);
+        // Writing instead of copying, since the originals are often read-only
+        fs::write(&dest, fs::read(path)?).context(format!(
+            "Failed to copy {} to {}",
+            path.display(),
+            dest.display()
+        ))?;
+        patchelf(&[OsStr::new("--set-soname"), OsStr::new(new_name)], &dest)?;
+        patchelf(&[OsStr::new("--set-rpath"), OsStr::new("$ORIGIN")], &dest)?;
+        replace_needed(&dest)?;
+        check_symbol_versions(&dest, target, manylinux)
+            .context(format!("{} is not manylinux compliant", path.display()))?;
+        println!("   - {} => {}", path.display(), new_name);
+        writer.add_file(libs_dir.join(new_name), &dest)?;
+    }
+
+    let mut runpath = "$ORIGIN".to_string();
+    for _ in artifact_dir.components() {
+        runpath += "/..";
+    }
+    runpath += &format!("/{}", libs_dir.display());
+
+    let patched_artifact = tempdir.join(artifact.file_name().unwrap());
+    fs::copy(artifact, &patched_artifact).context(format!(
+        "Failed to copy {} to {}",
+        artifact.display(),
+        patched_artifact.display()
+    ))?;
+    replace_needed(&patched_artifact)?;
+    patchelf(
+        &[OsStr::new("--set-rpath"), OsStr::new(&runpath)],
+        &patched_artifact,
+    )?;
+
+    Ok(patched_artifact)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{CargoToml, Metadata21, WheelWriter};
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    #[test]
+    fn test_hashed_name() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(file.path(), b"").unwrap();
+        // sha256 of the empty string starts with e3b0c442
+        assert_eq!(
+            hashed_name("libfoo.so.1", file.path()).unwrap(),
+            "libfoo-e3b0c442.so.1"
+        );
+        assert_eq!(
+            hashed_name("libbar", file.path()).unwrap(),
+            "libbar-e3b0c442"
+        );
+        assert_eq!(
+            hashed_name("libfoo.socket.so.1", file.path()).unwrap(),
+            "libfoo.socket-e3b0c442.so.1"
+        );
+        assert_eq!(
+            hashed_name("libfoo.so", file.path()).unwrap(),
+            "libfoo-e3b0c442.so"
+        );
+    }
+
+    /// Builds a library that links a library which isn't allowed by the policy with the C
+    /// compiler, repairs it into a wheel and checks that the dependency was vendored and the
+    /// library patched to load it. Skipped if patchelf or the C compiler isn't installed
+    #[test]
+    fn test_repair_wheel() {
+        let installed = |program: &str| Command::new(program).arg("--version").output().is_ok();
+        if !cfg!(target_os = "linux") || !installed("patchelf") || !installed("cc") {
+            return;
+        }
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+        fs::write(dir.join("dep.c"), "int dep(void) { return 42; }\n").unwrap();
+        fs::write(
+            dir.join("module.c"),
+            "int dep(void);\nint module(void) { return dep(); }\n",
+        )
+        .unwrap();
+        let cc = |args: &[&str]| {
+            let status = Command::new("cc")
+                .args(args)
+                .current_dir(dir)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        cc(&["-shared", "-fPIC", "-o", "libdep.so", "dep.c"]);
+        // The RUNPATH lets the repair find libdep.so like the dynamic linker would
+        let rpath = format!("-Wl,-rpath,{}", dir.display());
+        cc(&[
+            "-shared",
+            "-fPIC",
+            "-o",
+            "libmodule.so",
+            "module.c",
+            "-L.",
+            "-ldep",
+            &rpath,
+        ]);
+
+        let manifest_path = Path::new("test-crates/hello-world/Cargo.toml");
+        let cargo_toml = CargoToml::from_path(manifest_path).unwrap();
+        let metadata21 =
+            Metadata21::from_cargo_toml(&cargo_toml, manifest_path.parent().unwrap()).unwrap();
+        let tags = vec!["py3-none-linux_x86_64".to_string()];
+        let mut writer =
+            WheelWriter::new(&tags[0], dir, &metadata21, &HashMap::new(), &tags).unwrap();
+        let patched_dir = dir.join("patched");
+        fs::create_dir(&patched_dir).unwrap();
+        let patched = repair_artifact(
+            &mut writer,
+            &dir.join("libmodule.so"),
+            Path::new("module"),
+            "module",
+            &Target::from_target_triple(None).unwrap(),
+            &Manylinux::Manylinux2014,
+            &patched_dir,
+        )
+        .unwrap();
+        writer
+            .add_file(Path::new("module").join("libmodule.so"), &patched)
+            .unwrap();
+        let wheel_path = writer.finish().unwrap();
+
+        let vendored = hashed_name("libdep.so", &dir.join("libdep.so")).unwrap();
+        let mut archive = ZipArchive::new(File::open(&wheel_path).unwrap()).unwrap();
+        assert!(archive
+            .by_name(&format!("module.libs/{}", vendored))
+            .is_ok());
+
+        let unpacked = dir.join("unpacked").join("module");
+        fs::create_dir_all(&unpacked).unwrap();
+        let mut module = Vec::new();
+        archive
+            .by_name("module/libmodule.so")
+            .unwrap()
+            .read_to_end(&mut module)
+            .unwrap();
+        fs::write(unpacked.join("libmodule.so"), &module).unwrap();
+
+        let elf = Elf::parse(&module).unwrap();
+        assert!(elf.libraries.contains(&vendored.as_str()));
+        assert!(!elf.libraries.contains(&"libdep.so"));
+        let (_, rpaths) = read_elf_info(&unpacked.join("libmodule.so")).unwrap();
+        assert_eq!(rpaths, vec![unpacked.join("..").join("module.libs")]);
+    }
+}
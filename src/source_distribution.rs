@@ -1,52 +1,247 @@
-use crate::module_writer::ModuleWriter;
+use crate::fingerprint::{is_up_to_date, store_fingerprint, Fingerprint};
+use crate::module_writer::{sdist_path, sdist_prefix, source_date_epoch, ModuleWriter};
 use crate::{Metadata21, SDistWriter};
 use anyhow::{bail, format_err, Context, Result};
-use cargo_metadata::Metadata;
+use cargo_metadata::MetadataCommand;
+use regex::{Captures, Regex};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::{fs, str};
+use tempfile::TempDir;
+use walkdir::WalkDir;
 
-/// Checks if there a local/path dependencies which might not be included
-/// when building the source distribution.
-pub fn warn_on_local_deps(cargo_metadata: &Metadata) {
-    let root_package = cargo_metadata
+/// The directory in the source distribution that the path dependencies are copied to
+const LOCAL_DEPENDENCIES_DIR: &str = "local_dependencies";
+
+/// The directory in the source distribution that `--vendor` puts the dependencies in
+const VENDOR_DIR: &str = "vendor";
+
+/// The file in each vendored crate with the checksums that cargo verifies
+const CARGO_CHECKSUM: &str = ".cargo-checksum.json";
+
+/// A path dependency, which is copied into the source distribution
+#[derive(Debug, Clone)]
+struct PathDependency {
+    /// The canonicalized directory of the dependency's Cargo.toml
+    manifest_dir: PathBuf,
+    /// The directory in the source distribution, relative to its root
+    sdist_dir: PathBuf,
+}
+
+/// Where the contents of a file in the source distribution come from
+#[derive(Debug, Clone)]
+enum SDistSource {
+    /// A file that is copied
+    File(PathBuf),
+    /// Contents generated by maturin, e.g. a rewritten Cargo.toml
+    Generated(Vec<u8>),
+}
+
+/// The files of a source distribution by their path relative to its root, each with the reason
+/// why it is included, and the files that `sdist-exclude` removed
+#[derive(Debug, Default)]
+struct SDistFiles {
+    files: BTreeMap<PathBuf, (SDistSource, String)>,
+    excluded: BTreeMap<PathBuf, String>,
+    /// The vendored dependencies, which must exist until the files are written
+    vendor_dir: Option<TempDir>,
+}
+
+impl SDistFiles {
+    fn add_file(&mut self, target: impl AsRef<Path>, source: impl AsRef<Path>, reason: &str) {
+        self.files.insert(
+            target.as_ref().to_path_buf(),
+            (
+                SDistSource::File(source.as_ref().to_path_buf()),
+                reason.to_string(),
+            ),
+        );
+    }
+
+    fn add_bytes(&mut self, target: impl AsRef<Path>, bytes: &[u8], reason: &str) {
+        self.files.insert(
+            target.as_ref().to_path_buf(),
+            (SDistSource::Generated(bytes.to_vec()), reason.to_string()),
+        );
+    }
+}
+
+/// Returns the first of the `sdist-exclude` patterns that matches either the path of a file
+/// relative to the directory of its crate or its path in the source distribution
+fn excluded_by<'a>(
+    exclude: &'a [(String, glob::Pattern)],
+    relative: &Path,
+    target: &Path,
+) -> Option<&'a str> {
+    exclude
+        .iter()
+        .find(|(_, pattern)| pattern.matches_path(relative) || pattern.matches_path(target))
+        .map(|(pattern, _)| pattern.as_str())
+}
+
+/// Returns the path dependencies of the package, including those of other path dependencies,
+/// ordered by name
+fn find_path_dependencies(manifest_path: &Path) -> Result<BTreeMap<String, PathDependency>> {
+    let cargo_metadata = MetadataCommand::new()
+        .manifest_path(manifest_path)
+        .exec()
+        .context("Cargo metadata failed. Do you have cargo in your PATH?")?;
+    let resolve = cargo_metadata
         .resolve
-        .clone()
-        .and_then(|y| y.root)
-        .expect("Expected a resolve with a root");
+        .as_ref()
+        .context("Expected cargo metadata to contain a resolve")?;
+    let root = resolve
+        .root
+        .as_ref()
+        .context("Expected a resolve with a root")?;
+
+    // Walks the dependency graph from the root, since in a workspace there are packages without
+    // a source which aren't dependencies of the root
+    let mut reachable = HashSet::new();
+    let mut stack = vec![root];
+    while let Some(id) = stack.pop() {
+        if let Some(node) = resolve.nodes.iter().find(|node| &node.id == id) {
+            for dependency in &node.dependencies {
+                if reachable.insert(dependency) {
+                    stack.push(dependency);
+                }
+            }
+        }
+    }
+
+    let mut path_dependencies = BTreeMap::new();
+    for package in &cargo_metadata.packages {
+        if package.source.is_some() || &package.id == root || !reachable.contains(&package.id) {
+            continue;
+        }
+        let manifest_dir = package.manifest_path.parent().unwrap();
+        let path_dependency = PathDependency {
+            manifest_dir: manifest_dir
+                .canonicalize()
+                .context(format!("Can't find {}", manifest_dir.display()))?,
+            sdist_dir: Path::new(LOCAL_DEPENDENCIES_DIR).join(&package.name),
+        };
+        if path_dependencies
+            .insert(package.name.clone(), path_dependency)
+            .is_some()
+        {
+            bail!(
+                "There are two path dependencies named {}, which can't both be included in \
+                 the source distribution",
+                package.name
+            );
+        }
+    }
+    Ok(path_dependencies)
+}
+
+/// Returns the path of a path dependency in the source distribution, relative to `to_root`,
+/// if the `path` of a dependency relative to `manifest_dir` points to one that is shipped
+fn sdist_dependency_path(
+    path: &str,
+    manifest_dir: &Path,
+    to_root: &Path,
+    path_dependencies: &BTreeMap<String, PathDependency>,
+) -> Option<String> {
+    let dependency_dir = manifest_dir.join(path).canonicalize().ok()?;
+    path_dependencies
+        .values()
+        .find(|path_dependency| path_dependency.manifest_dir == dependency_dir)
+        .map(|path_dependency| {
+            to_root
+                .join(&path_dependency.sdist_dir)
+                .to_str()
+                .unwrap()
+                .replace("\\", "/")
+        })
+}
+
+/// Points the `path` of the path dependencies in a Cargo.toml to their location in the
+/// source distribution. `manifest_dir` is the original location of the Cargo.toml and
+/// `sdist_dir` the one in the source distribution
+///
+/// Only the values of the `path` keys are replaced in the text, so that the comments, the
+/// formatting and the order of the Cargo.toml are kept
+fn rewrite_path_dependencies(
+    contents: &str,
+    manifest_dir: &Path,
+    sdist_dir: &Path,
+    path_dependencies: &BTreeMap<String, PathDependency>,
+) -> Result<String> {
+    let manifest: toml::value::Table =
+        toml::from_str(contents).context("Failed to parse Cargo.toml")?;
+    let manifest_dir = manifest_dir
+        .canonicalize()
+        .context(format!("Can't find {}", manifest_dir.display()))?;
+    // Going up to the root of the source distribution, from where the dependencies are referenced
+    let to_root: PathBuf = sdist_dir.components().map(|_| "..").collect();
 
-    let local_deps: Vec<String> = cargo_metadata
-        .packages
+    let dependency_keys = ["dependencies", "dev-dependencies", "build-dependencies"];
+    let mut dependency_tables: Vec<&toml::Value> = dependency_keys
         .iter()
-        .filter(|x| x.source.is_none())
-        // Remove the package itself
-        .filter(|x| x.id != root_package)
-        .map(|x| x.name.clone())
+        .filter_map(|key| manifest.get(*key))
         .collect();
-    if !local_deps.is_empty() {
-        eprintln!(
-            "⚠ The are local dependencies, which the source distribution might not include: {}",
-            local_deps.join(", ")
-        );
+    // Platform specific dependencies, e.g. `[target.'cfg(unix)'.dependencies]`
+    if let Some(toml::Value::Table(targets)) = manifest.get("target") {
+        for target in targets.values() {
+            dependency_tables.extend(dependency_keys.iter().filter_map(|key| target.get(*key)));
+        }
+    }
+    // The patches of each source, e.g. `[patch.crates-io]`, and the deprecated `[replace]`
+    if let Some(toml::Value::Table(patches)) = manifest.get("patch") {
+        dependency_tables.extend(patches.values());
     }
+    dependency_tables.extend(manifest.get("replace"));
+
+    // The old path mapped to the new one. All paths are relative to the manifest, so the same
+    // old path always becomes the same new path
+    let mut rewrites: HashMap<String, String> = HashMap::new();
+    for dependencies in dependency_tables {
+        let dependencies = match dependencies {
+            toml::Value::Table(dependencies) => dependencies,
+            _ => continue,
+        };
+        for dependency in dependencies.values() {
+            let path = match dependency.get("path") {
+                Some(toml::Value::String(path)) => path,
+                _ => continue,
+            };
+            if let Some(new_path) =
+                sdist_dependency_path(path, &manifest_dir, &to_root, path_dependencies)
+            {
+                rewrites.insert(path.clone(), new_path);
+            }
+        }
+    }
+
+    // `path = "..."` as key in a table, in an inline table or as dotted key, with a basic or a
+    // literal string as value
+    let path_value =
+        Regex::new(r#"(?m)((?:^|[\s{,.])path\s*=\s*)("(?:[^"\\\n]|\\.)*"|'[^'\n]*')"#).unwrap();
+    let rewritten = path_value.replace_all(contents, |captures: &Captures| {
+        let value = toml::from_str::<toml::value::Table>(&format!("value = {}", &captures[2]))
+            .ok()
+            .and_then(|table| {
+                table
+                    .get("value")
+                    .and_then(|value| value.as_str().map(ToString::to_string))
+            });
+        match value.and_then(|value| rewrites.get(&value)) {
+            Some(new_path) => format!("{}{}", &captures[1], toml::Value::String(new_path.clone())),
+            None => captures[0].to_string(),
+        }
+    });
+    Ok(rewritten.into_owned())
 }
 
-/// Creates a source distribution
-///
-/// Runs `cargo package --list --allow-dirty` to obtain a list of files to package.
-///
-/// The source distribution format is specified in
-/// [PEP 517 under "build_sdist"](https://www.python.org/dev/peps/pep-0517/#build-sdist)
-pub fn source_distribution(
-    wheel_dir: impl AsRef<Path>,
-    metadata21: &Metadata21,
-    manifest_path: impl AsRef<Path>,
-    sdist_include: Option<&Vec<String>>,
-) -> Result<PathBuf> {
+/// Runs `cargo package --list --allow-dirty` to obtain the files to package, as pairs of the
+/// path relative to the manifest and the path relative to the current directory
+fn cargo_package_files(manifest_path: &Path) -> Result<Vec<(PathBuf, PathBuf)>> {
     let output = Command::new("cargo")
         .args(&["package", "--list", "--allow-dirty", "--manifest-path"])
-        .arg(manifest_path.as_ref())
+        .arg(manifest_path)
         .output()
         .context("Failed to run cargo")?;
     if !output.status.success() {
@@ -58,53 +253,391 @@ pub fn source_distribution(
         );
     }
 
-    let file_list: Vec<&Path> = str::from_utf8(&output.stdout)
+    let manifest_dir = manifest_path.parent().unwrap();
+    let target_source = str::from_utf8(&output.stdout)
         .context("Cargo printed invalid utf-8 ಠ_ಠ")?
         .lines()
-        .map(Path::new)
-        .collect();
-
-    let manifest_dir = manifest_path.as_ref().parent().unwrap();
-
-    let target_source: Vec<(PathBuf, PathBuf)> = file_list
-        .iter()
         .map(|relative_to_manifests| {
             let relative_to_cwd = manifest_dir.join(relative_to_manifests);
-            (relative_to_manifests.to_path_buf(), relative_to_cwd)
+            (PathBuf::from(relative_to_manifests), relative_to_cwd)
         })
-        .filter(|(target, _)| target != Path::new("Cargo.toml.orig"))
+        // `cargo package` also lists the files it generates, such as Cargo.toml.orig,
+        // .cargo_vcs_info.json or the Cargo.lock of a workspace member, which don't exist in the
+        // source tree
+        .filter(|(_, source)| source.is_file())
         .collect();
+    Ok(target_source)
+}
+
+/// Adds the files of a crate to the source distribution at `sdist_dir`, with the path
+/// dependencies in its Cargo.toml pointing to their copies, unless they match one of the
+/// `exclude` patterns
+fn add_crate_files(
+    files: &mut SDistFiles,
+    target_source: Vec<(PathBuf, PathBuf)>,
+    manifest_dir: &Path,
+    sdist_dir: &Path,
+    path_dependencies: &BTreeMap<String, PathDependency>,
+    exclude: &[(String, glob::Pattern)],
+    reason: &str,
+) -> Result<()> {
+    for (target, source) in target_source {
+        if let Some(pattern) = excluded_by(exclude, &target, &sdist_dir.join(&target)) {
+            files
+                .excluded
+                .insert(sdist_dir.join(target), pattern.to_string());
+        } else if target == Path::new("Cargo.toml") && !path_dependencies.is_empty() {
+            let contents = fs::read_to_string(&source)
+                .context(format!("Failed to read {}", source.display()))?;
+            let rewritten =
+                rewrite_path_dependencies(&contents, manifest_dir, sdist_dir, path_dependencies)
+                    .context(format!(
+                        "Failed to rewrite the path dependencies in {}",
+                        source.display()
+                    ))?;
+            let reason = format!("{}, with the path dependencies rewritten", reason);
+            files.add_bytes(sdist_dir.join(target), rewritten.as_bytes(), &reason);
+        } else {
+            files.add_file(sdist_dir.join(target), source, reason);
+        }
+    }
+    Ok(())
+}
+
+/// Turns the source replacement that `cargo vendor` prints into a `.cargo/config.toml` that
+/// points to the vendor directory relative to the root of the source distribution
+fn vendor_config(cargo_vendor_output: &str) -> Result<String> {
+    let mut config: toml::value::Table = toml::from_str(cargo_vendor_output)
+        .context("Failed to parse the source replacement printed by cargo vendor")?;
+    let sources = match config.get_mut("source") {
+        Some(toml::Value::Table(sources)) => sources,
+        _ => bail!("cargo vendor didn't print a source replacement"),
+    };
+    for (_, source) in sources.iter_mut() {
+        if let Some(directory) = source.get_mut("directory") {
+            *directory = toml::Value::String(VENDOR_DIR.to_string());
+        }
+    }
+    Ok(toml::to_string(&config)?)
+}
+
+/// Returns the files in the directory that aren't ignored by a `.gitignore`, `.git/info/exclude`
+/// or the global gitignore, relative to the directory
+fn not_ignored_files(dir: &Path) -> Result<HashSet<PathBuf>> {
+    // An empty path means the current directory, which the walker can't open
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let mut files = HashSet::new();
+    let walker = ignore::WalkBuilder::new(dir)
+        .hidden(false)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build();
+    for entry in walker {
+        let entry = entry.context(format!("Failed to list the files in {}", dir.display()))?;
+        if entry.file_type().map(|file_type| file_type.is_file()) == Some(true) {
+            files.insert(entry.path().strip_prefix(dir)?.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+/// Runs `cargo vendor` and adds the vendored crates to the `vendor` directory of the source
+/// distribution, together with a `.cargo/config.toml` that makes cargo use them instead of
+/// crates.io and git
+///
+/// The lock file is always added, even if it is excluded, since `--locked` can't build the
+/// vendored crates without the versions that `cargo vendor` resolved. The checksums of files
+/// that `exclude` removes from a vendored crate are dropped from its `.cargo-checksum.json`,
+/// because cargo would otherwise refuse to build it.
+fn vendor_dependencies(
+    files: &mut SDistFiles,
+    manifest_path: &Path,
+    sdist_exclude: Option<&Vec<String>>,
+) -> Result<()> {
+    let lock_file = vendor_lock_file(manifest_path)?;
+    let exclude = exclude_patterns(sdist_exclude)?;
+    println!("📦 Vendoring the dependencies");
+    let tempdir = tempfile::tempdir()?;
+    let vendor_dir = tempdir.path().join(VENDOR_DIR);
+    let output = Command::new("cargo")
+        .args(&["vendor", "--manifest-path"])
+        .arg(manifest_path)
+        .arg(&vendor_dir)
+        .output()
+        .context("Failed to run cargo vendor")?;
+    if !output.status.success() {
+        bail!(
+            "Failed to vendor the dependencies: {}\n--- Stdout:\n{}\n--- Stderr:\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr),
+        );
+    }
+    let stdout = str::from_utf8(&output.stdout).context("Cargo printed invalid utf-8 ಠ_ಠ")?;
+    // Without dependencies from crates.io or git, cargo vendor prints no source replacement,
+    // and the source distribution can be built offline as it is
+    if stdout.trim().is_empty() {
+        println!("📦 There are no dependencies to vendor");
+        return Ok(());
+    }
+    let config = vendor_config(stdout)?;
+
+    let reason = "vendored by `cargo vendor`";
+    for crate_dir in WalkDir::new(&vendor_dir).min_depth(1).max_depth(1) {
+        let crate_dir = crate_dir?;
+        if !crate_dir.file_type().is_dir() {
+            continue;
+        }
+        let crate_dir = crate_dir.into_path();
+        let mut excluded = Vec::new();
+        for entry in WalkDir::new(&crate_dir) {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(&crate_dir)?;
+            let target = entry.path().strip_prefix(tempdir.path())?;
+            match excluded_by(&exclude, relative, target) {
+                Some(pattern) if relative != Path::new(CARGO_CHECKSUM) => {
+                    files
+                        .excluded
+                        .insert(target.to_path_buf(), pattern.to_string());
+                    excluded.push(relative.to_str().unwrap().replace("\\", "/"));
+                }
+                _ => files.add_file(target, entry.path(), reason),
+            }
+        }
+
+        if !excluded.is_empty() {
+            let checksum_path = crate_dir.join(CARGO_CHECKSUM);
+            let mut checksums: serde_json::Value =
+                serde_json::from_str(&fs::read_to_string(&checksum_path)?)
+                    .context(format!("Failed to parse {}", checksum_path.display()))?;
+            if let Some(checksum_files) = checksums
+                .get_mut("files")
+                .and_then(serde_json::Value::as_object_mut)
+            {
+                for relative in &excluded {
+                    checksum_files.remove(relative);
+                }
+            }
+            files.add_bytes(
+                checksum_path.strip_prefix(tempdir.path())?,
+                serde_json::to_string(&checksums)?.as_bytes(),
+                &format!("{}, without the checksums of the excluded files", reason),
+            );
+        }
+    }
+    files.add_bytes(
+        Path::new(".cargo").join("config.toml"),
+        config.as_bytes(),
+        "source replacement for the vendored dependencies",
+    );
+    // cargo vendor creates the lock file if there was none
+    files.excluded.remove(Path::new("Cargo.lock"));
+    files.add_file(
+        "Cargo.lock",
+        lock_file,
+        "the lock file of the vendored dependencies",
+    );
+    files.vendor_dir = Some(tempdir);
+    Ok(())
+}
+
+/// Returns true if the directory is the root of a source distribution with vendored
+/// dependencies, which then must be built with `--offline --locked`
+pub fn is_vendored(sdist_root: impl AsRef<Path>) -> bool {
+    sdist_root.as_ref().join(VENDOR_DIR).is_dir()
+        && sdist_root
+            .as_ref()
+            .join(".cargo")
+            .join("config.toml")
+            .is_file()
+}
 
-    if !target_source
+/// Returns the lock file that cargo uses for the package, which is the one of the workspace root
+/// for a workspace member
+fn vendor_lock_file(manifest_path: &Path) -> Result<PathBuf> {
+    let manifest_path = manifest_path
+        .canonicalize()
+        .context(format!("Can't find {}", manifest_path.display()))?;
+    let lock_root = match find_workspace(&manifest_path)? {
+        Some((workspace_root, _)) => workspace_root,
+        None => manifest_path.parent().unwrap().to_path_buf(),
+    };
+    Ok(lock_root.join("Cargo.lock"))
+}
+
+/// Compiles the `sdist-exclude` globs
+fn exclude_patterns(sdist_exclude: Option<&Vec<String>>) -> Result<Vec<(String, glob::Pattern)>> {
+    sdist_exclude
+        .map(Vec::as_slice)
+        .unwrap_or_default()
         .iter()
-        .any(|(target, _)| target == Path::new("pyproject.toml"))
-    {
+        .map(|pattern| {
+            let compiled = glob::Pattern::new(pattern)
+                .context(format!("Invalid sdist-exclude pattern \"{}\"", pattern))?;
+            Ok((pattern.clone(), compiled))
+        })
+        .collect()
+}
+
+/// Collects the files of the source distribution, except for the vendored dependencies, which
+/// [vendor_dependencies] adds
+///
+/// Runs `cargo package --list --allow-dirty` to obtain a list of files to package. The path
+/// dependencies are included in the `local_dependencies` directory.
+///
+/// The `sdist_include` and `sdist_exclude` globs are relative to the directory of the
+/// Cargo.toml. Files matching `sdist_exclude` are removed from those listed by cargo and those
+/// matching `sdist_include`.
+fn sdist_files(
+    metadata21: &Metadata21,
+    manifest_path: &Path,
+    sdist_include: Option<&Vec<String>>,
+    sdist_exclude: Option<&Vec<String>>,
+) -> Result<SDistFiles> {
+    // The parent of a plain `Cargo.toml` is an empty path, which can't be canonicalized
+    let manifest_path = &manifest_path
+        .canonicalize()
+        .context(format!("Can't find {}", manifest_path.display()))?;
+    let manifest_dir = manifest_path.parent().unwrap();
+    let workspace = find_workspace(manifest_path)?;
+    // Workspace members are placed at their location relative to the workspace root
+    let member_dir = match workspace {
+        Some((_, ref member_dir)) => member_dir.clone(),
+        None => PathBuf::new(),
+    };
+
+    let exclude = exclude_patterns(sdist_exclude)?;
+
+    let mut files = SDistFiles::default();
+    let target_source = cargo_package_files(manifest_path)?;
+    let path_dependencies = find_path_dependencies(manifest_path)?;
+    add_crate_files(
+        &mut files,
+        target_source,
+        manifest_dir,
+        &member_dir,
+        &path_dependencies,
+        &exclude,
+        "listed by `cargo package`",
+    )?;
+
+    if !files.files.contains_key(&member_dir.join("pyproject.toml")) {
         bail!(
-            "pyproject.toml was not included by `cargo package`. \
-             Please make sure pyproject.toml is not excluded or build with `--no-sdist`"
+            "pyproject.toml was not included by `cargo package` or is excluded by \
+             `sdist-exclude`. Please make sure pyproject.toml is included or build with \
+             `--no-sdist`"
         )
     }
 
-    let mut writer = SDistWriter::new(wheel_dir, &metadata21)?;
-    for (target, source) in target_source {
-        writer.add_file(target, source)?;
+    for (name, path_dependency) in &path_dependencies {
+        println!("📦 Including path dependency {}", name);
+        let dependency_manifest = path_dependency.manifest_dir.join("Cargo.toml");
+        // Cargo only reads the lock file of the package that is built
+        let target_source = cargo_package_files(&dependency_manifest)?
+            .into_iter()
+            .filter(|(target, _)| target != Path::new("Cargo.lock"))
+            .collect();
+        add_crate_files(
+            &mut files,
+            target_source,
+            &path_dependency.manifest_dir,
+            &path_dependency.sdist_dir,
+            &path_dependencies,
+            &exclude,
+            &format!("path dependency {}", name),
+        )?;
+    }
+
+    if let Some((ref workspace_root, ref member_dir)) = workspace {
+        add_workspace_files(
+            &mut files,
+            workspace_root,
+            member_dir,
+            manifest_dir,
+            &path_dependencies,
+        )?;
     }
 
     if let Some(include_targets) = sdist_include {
+        let not_ignored = not_ignored_files(manifest_dir)?;
+        // Special characters in the path of the manifest directory must not act as globs
+        let escaped_manifest_dir = glob::Pattern::escape(manifest_dir.to_str().unwrap());
         for pattern in include_targets {
+            // A pattern without wildcards names a single file, which is included even if it
+            // is ignored, e.g. because it is generated
+            let is_literal = !pattern.contains(&['*', '?', '['][..]);
             println!("📦 Including files matching \"{}\"", pattern);
-            for source in glob::glob(pattern)
-                .expect("No files found for pattern")
-                .filter_map(Result::ok)
-            {
-                writer.add_file(manifest_dir.join(&source).to_path_buf(), source)?;
+            let reason = format!("matches sdist-include \"{}\"", pattern);
+            let pattern_path = Path::new(&escaped_manifest_dir).join(pattern);
+            let sources = glob::glob(pattern_path.to_str().unwrap())
+                .context(format!("Invalid sdist-include pattern \"{}\"", pattern))?;
+            for source in sources.filter_map(Result::ok) {
+                if !source.is_file() {
+                    continue;
+                }
+                let relative = source.strip_prefix(manifest_dir)?.to_path_buf();
+                let target = member_dir.join(&relative);
+                if let Some(pattern) = excluded_by(&exclude, &relative, &target) {
+                    files.excluded.insert(target, pattern.to_string());
+                } else if !is_literal && !not_ignored.contains(&relative) {
+                    continue;
+                } else {
+                    files.add_file(member_dir.join(relative), source, &reason);
+                }
             }
         }
     }
 
-    writer.add_bytes("PKG-INFO", metadata21.to_file_contents().as_bytes())?;
+    files.add_bytes(
+        "PKG-INFO",
+        metadata21.to_file_contents().as_bytes(),
+        "the python package metadata",
+    );
+
+    Ok(files)
+}
 
+/// Creates a source distribution with the files described in [sdist_files]
+///
+/// The source distribution format is specified in
+/// [PEP 517 under "build_sdist"](https://www.python.org/dev/peps/pep-0517/#build-sdist)
+pub fn source_distribution(
+    wheel_dir: impl AsRef<Path>,
+    metadata21: &Metadata21,
+    manifest_path: impl AsRef<Path>,
+    sdist_include: Option<&Vec<String>>,
+    sdist_exclude: Option<&Vec<String>>,
+    vendor: bool,
+) -> Result<PathBuf> {
+    let manifest_path = manifest_path.as_ref();
+    let mut files = sdist_files(metadata21, manifest_path, sdist_include, sdist_exclude)?;
+
+    let fingerprint = sdist_fingerprint(&files, manifest_path, sdist_exclude, vendor)?;
+    let path = sdist_path(wheel_dir.as_ref(), metadata21);
+    if is_up_to_date(&path, &fingerprint) {
+        println!("📦 {} is up to date", path.display());
+        return Ok(path);
+    }
+
+    if vendor {
+        vendor_dependencies(&mut files, manifest_path, sdist_exclude)?;
+    }
+
+    let mut writer = SDistWriter::new(wheel_dir, metadata21)?;
+    for (target, (source, _)) in &files.files {
+        match source {
+            SDistSource::File(source) => writer.add_file(target, source)?,
+            SDistSource::Generated(bytes) => writer.add_bytes(target, bytes)?,
+        }
+    }
     let source_distribution_path = writer.finish()?;
+    store_fingerprint(&source_distribution_path, &fingerprint)?;
 
     println!(
         "📦 Built source distribution to {}",
@@ -114,6 +647,230 @@ pub fn source_distribution(
     Ok(source_distribution_path)
 }
 
+/// Returns the fingerprint of the files of the source distribution and `SOURCE_DATE_EPOCH`
+///
+/// The vendored crates are represented by the lock file they are resolved from and the
+/// `sdist_exclude` patterns, so that an unchanged source distribution is reused without running
+/// `cargo vendor`
+fn sdist_fingerprint(
+    files: &SDistFiles,
+    manifest_path: &Path,
+    sdist_exclude: Option<&Vec<String>>,
+    vendor: bool,
+) -> Result<String> {
+    let mut fingerprint = Fingerprint::new();
+    fingerprint.add_str(&source_date_epoch()?.to_string());
+    for (target, (source, _)) in &files.files {
+        fingerprint.add_str(&target.to_string_lossy());
+        match source {
+            SDistSource::File(source) => fingerprint.add_file(source)?,
+            SDistSource::Generated(bytes) => fingerprint.add_bytes(bytes),
+        }
+    }
+    fingerprint.add_serialized(&vendor)?;
+    if vendor {
+        fingerprint.add_serialized(&sdist_exclude)?;
+        fingerprint.add_optional_file(&vendor_lock_file(manifest_path)?)?;
+    }
+    Ok(fingerprint.finish())
+}
+
+/// Prints the files that [source_distribution] would package and why, as well as the files
+/// that `sdist_exclude` removed, without building the source distribution
+pub fn list_source_distribution(
+    metadata21: &Metadata21,
+    manifest_path: impl AsRef<Path>,
+    sdist_include: Option<&Vec<String>>,
+    sdist_exclude: Option<&Vec<String>>,
+    vendor: bool,
+) -> Result<()> {
+    let mut files = sdist_files(
+        metadata21,
+        manifest_path.as_ref(),
+        sdist_include,
+        sdist_exclude,
+    )?;
+    if vendor {
+        vendor_dependencies(&mut files, manifest_path.as_ref(), sdist_exclude)?;
+    }
+
+    let prefix = sdist_prefix(metadata21);
+    println!("📦 The source distribution would contain:");
+    for (target, (_, reason)) in &files.files {
+        println!("{}: {}", prefix.join(target).display(), reason);
+    }
+    if !files.excluded.is_empty() {
+        println!("🗑  Excluded:");
+        for (target, pattern) in &files.excluded {
+            println!(
+                "{}: matches sdist-exclude \"{}\"",
+                prefix.join(target).display(),
+                pattern
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns the workspace root and the path of the package relative to it if the package is a
+/// member of a workspace other than its own
+fn find_workspace(manifest_path: &Path) -> Result<Option<(PathBuf, PathBuf)>> {
+    let cargo_metadata = MetadataCommand::new()
+        .manifest_path(manifest_path)
+        .no_deps()
+        .exec()
+        .context("Cargo metadata failed. Do you have cargo in your PATH?")?;
+    let manifest_dir = manifest_path
+        .parent()
+        .unwrap()
+        .canonicalize()
+        .context(format!("Can't find {}", manifest_path.display()))?;
+    let workspace_root = cargo_metadata.workspace_root.canonicalize()?;
+    if manifest_dir == workspace_root {
+        return Ok(None);
+    }
+    let member_dir = manifest_dir
+        .strip_prefix(&workspace_root)
+        .context("The package must be inside of its workspace")?
+        .to_path_buf();
+    Ok(Some((workspace_root, member_dir)))
+}
+
+/// Turns the manifest of the workspace root into a virtual manifest that only contains the
+/// member that is packaged, since the other members and a root package aren't part of the
+/// source distribution
+///
+/// The path patches of the workspace point to the copies of the path dependencies, or are
+/// dropped if the member doesn't depend on them, since cargo fails on patches that don't exist
+fn rewrite_workspace_manifest(
+    contents: &str,
+    workspace_root: &Path,
+    member_dir: &Path,
+    path_dependencies: &BTreeMap<String, PathDependency>,
+) -> Result<String> {
+    let manifest: toml::value::Table =
+        toml::from_str(contents).context("Failed to parse the workspace Cargo.toml")?;
+    let mut rewritten = toml::value::Table::new();
+    // The sections that apply to the whole workspace
+    for key in &["workspace", "patch", "replace", "profile"] {
+        if let Some(value) = manifest.get(*key) {
+            rewritten.insert(key.to_string(), value.clone());
+        }
+    }
+    let mut workspace = match rewritten.remove("workspace") {
+        Some(toml::Value::Table(workspace)) => workspace,
+        _ => bail!("The workspace Cargo.toml doesn't have a [workspace] section"),
+    };
+    let member = member_dir.to_str().unwrap().replace("\\", "/");
+    workspace.insert(
+        "members".to_string(),
+        toml::Value::Array(vec![toml::Value::String(member)]),
+    );
+    workspace.remove("default-members");
+    workspace.remove("exclude");
+    rewritten.insert("workspace".to_string(), toml::Value::Table(workspace));
+
+    if let Some(toml::Value::Table(patches)) = rewritten.get_mut("patch") {
+        for (_, patch) in patches.iter_mut() {
+            if let toml::Value::Table(patch) = patch {
+                rewrite_patch_paths(patch, workspace_root, path_dependencies);
+            }
+        }
+    }
+    if let Some(toml::Value::Table(replace)) = rewritten.get_mut("replace") {
+        rewrite_patch_paths(replace, workspace_root, path_dependencies);
+    }
+    Ok(toml::to_string(&rewritten)?)
+}
+
+/// Points the path patches of a `[patch.<source>]` or `[replace]` table of the workspace root
+/// to their location in the source distribution and removes those that aren't shipped
+fn rewrite_patch_paths(
+    patches: &mut toml::value::Table,
+    workspace_root: &Path,
+    path_dependencies: &BTreeMap<String, PathDependency>,
+) {
+    let mut unused = Vec::new();
+    for (name, patch) in patches.iter_mut() {
+        let path = match patch.get("path") {
+            Some(toml::Value::String(path)) => path.clone(),
+            _ => continue,
+        };
+        // The workspace manifest is at the root of the source distribution
+        match sdist_dependency_path(&path, workspace_root, Path::new(""), path_dependencies) {
+            Some(new_path) => {
+                patch
+                    .as_table_mut()
+                    .unwrap()
+                    .insert("path".to_string(), toml::Value::String(new_path));
+            }
+            None => unused.push(name.clone()),
+        }
+    }
+    for name in unused {
+        patches.remove(&name);
+    }
+}
+
+/// Adds the manifest and the lock file of the workspace root, as well as a pyproject.toml that
+/// points maturin to the manifest of the member, to the root of the source distribution
+fn add_workspace_files(
+    files: &mut SDistFiles,
+    workspace_root: &Path,
+    member_dir: &Path,
+    manifest_dir: &Path,
+    path_dependencies: &BTreeMap<String, PathDependency>,
+) -> Result<()> {
+    let workspace_manifest = workspace_root.join("Cargo.toml");
+    let contents = fs::read_to_string(&workspace_manifest)
+        .context(format!("Failed to read {}", workspace_manifest.display()))?;
+    let rewritten =
+        rewrite_workspace_manifest(&contents, workspace_root, member_dir, path_dependencies)?;
+    files.add_bytes(
+        "Cargo.toml",
+        rewritten.as_bytes(),
+        "the workspace manifest, reduced to the member",
+    );
+
+    let lock_file = workspace_root.join("Cargo.lock");
+    if lock_file.is_file() {
+        files.add_file("Cargo.lock", lock_file, "the lock file of the workspace");
+    }
+
+    let pyproject_path = manifest_dir.join("pyproject.toml");
+    let contents = fs::read_to_string(&pyproject_path)
+        .context(format!("Failed to read {}", pyproject_path.display()))?;
+    let mut pyproject: toml::value::Table =
+        toml::from_str(&contents).context("Failed to parse pyproject.toml")?;
+    let manifest_path = member_dir
+        .join("Cargo.toml")
+        .to_str()
+        .unwrap()
+        .replace("\\", "/");
+    let tool = pyproject
+        .entry("tool")
+        .or_insert_with(|| toml::Value::Table(Default::default()));
+    let maturin = tool
+        .as_table_mut()
+        .context("`tool` in pyproject.toml must be a table")?
+        .entry("maturin")
+        .or_insert_with(|| toml::Value::Table(Default::default()));
+    maturin
+        .as_table_mut()
+        .context("`tool.maturin` in pyproject.toml must be a table")?
+        .insert(
+            "manifest-path".to_string(),
+            toml::Value::String(manifest_path),
+        );
+    files.add_bytes(
+        "pyproject.toml",
+        toml::to_string(&pyproject)?.as_bytes(),
+        "pyproject.toml pointing to the workspace member",
+    );
+
+    Ok(())
+}
+
 /// The `[build-system]` section of a pyproject.toml as specified in PEP 517
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
@@ -134,6 +891,7 @@ pub struct Tool {
 #[serde(rename_all = "kebab-case")]
 pub struct ToolMaturin {
     sdist_include: Option<Vec<String>>,
+    sdist_exclude: Option<Vec<String>>,
 }
 
 /// A pyproject.toml as specified in PEP 517
@@ -148,6 +906,10 @@ impl PyProjectToml {
     pub fn sdist_include(&self) -> Option<&Vec<String>> {
         self.tool.as_ref()?.maturin.as_ref()?.sdist_include.as_ref()
     }
+
+    pub fn sdist_exclude(&self) -> Option<&Vec<String>> {
+        self.tool.as_ref()?.maturin.as_ref()?.sdist_exclude.as_ref()
+    }
 }
 
 /// Returns the contents of a pyproject.toml with a `[build-system]` entry or an error
@@ -164,3 +926,303 @@ pub fn get_pyproject_toml(project_root: impl AsRef<Path>) -> Result<PyProjectTom
         .map_err(|err| format_err!("pyproject.toml is not PEP 517 compliant: {}", err))?;
     Ok(cargo_toml)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::CargoToml;
+
+    #[test]
+    fn test_rewrite_workspace_manifest() {
+        let manifest = r#"
+[workspace]
+members = ["crates/*"]
+exclude = ["crates/excluded"]
+
+[package]
+name = "root"
+version = "0.1.0"
+
+[profile.release]
+lto = true
+
+[patch.crates-io]
+some_path_dep = { path = "some_path_dep" }
+unused = { path = "crates/unused" }
+pyo3 = { git = "https://github.com/PyO3/pyo3" }
+
+[replace]
+"some_path_dep:0.1.0" = { path = "some_path_dep" }
+"#;
+        let mut path_dependencies = BTreeMap::new();
+        path_dependencies.insert(
+            "some_path_dep".to_string(),
+            PathDependency {
+                manifest_dir: Path::new("test-crates/some_path_dep")
+                    .canonicalize()
+                    .unwrap(),
+                sdist_dir: Path::new("local_dependencies").join("some_path_dep"),
+            },
+        );
+        let rewritten = rewrite_workspace_manifest(
+            manifest,
+            Path::new("test-crates"),
+            &Path::new("crates").join("py"),
+            &path_dependencies,
+        )
+        .unwrap();
+        let rewritten: toml::Value = toml::from_str(&rewritten).unwrap();
+        let expected: toml::Value = toml::from_str(
+            r#"
+[workspace]
+members = ["crates/py"]
+
+[profile.release]
+lto = true
+
+[patch.crates-io]
+some_path_dep = { path = "local_dependencies/some_path_dep" }
+pyo3 = { git = "https://github.com/PyO3/pyo3" }
+
+[replace]
+"some_path_dep:0.1.0" = { path = "local_dependencies/some_path_dep" }
+"#,
+        )
+        .unwrap();
+        assert_eq!(rewritten, expected);
+    }
+
+    #[test]
+    fn test_find_path_dependencies() {
+        let path_dependencies =
+            find_path_dependencies(Path::new("test-crates/lib_with_path_dep/Cargo.toml")).unwrap();
+        assert_eq!(
+            path_dependencies.keys().collect::<Vec<_>>(),
+            vec!["some_path_dep"]
+        );
+        let some_path_dep = &path_dependencies["some_path_dep"];
+        assert_eq!(
+            some_path_dep.manifest_dir,
+            Path::new("test-crates/some_path_dep")
+                .canonicalize()
+                .unwrap()
+        );
+        assert_eq!(
+            some_path_dep.sdist_dir,
+            Path::new("local_dependencies").join("some_path_dep")
+        );
+    }
+
+    #[test]
+    fn test_rewrite_path_dependencies() {
+        let manifest = r#"
+[package]
+name = "lib_with_path_dep"
+version = "0.1.0"
+
+# The comments, the formatting and the order must be kept
+[dependencies]
+some_path_dep = { path = "../some_path_dep" }
+lazy_static = "1.4.0"
+
+[target.'cfg(unix)'.dev-dependencies.some_path_dep]
+path = '../some_path_dep' # literal string
+features = []
+
+[lib]
+path = "src/lib.rs"
+
+[patch.crates-io]
+some_path_dep = { path = "../some_path_dep" }
+
+[replace]
+"some_path_dep:0.1.0" = { path = "../some_path_dep" }
+"#;
+        let mut path_dependencies = BTreeMap::new();
+        path_dependencies.insert(
+            "some_path_dep".to_string(),
+            PathDependency {
+                manifest_dir: Path::new("test-crates/some_path_dep")
+                    .canonicalize()
+                    .unwrap(),
+                sdist_dir: Path::new("local_dependencies").join("some_path_dep"),
+            },
+        );
+        let rewritten = rewrite_path_dependencies(
+            manifest,
+            Path::new("test-crates/lib_with_path_dep"),
+            &Path::new("crates").join("py"),
+            &path_dependencies,
+        )
+        .unwrap();
+        let expected = r#"
+[package]
+name = "lib_with_path_dep"
+version = "0.1.0"
+
+# The comments, the formatting and the order must be kept
+[dependencies]
+some_path_dep = { path = "../../local_dependencies/some_path_dep" }
+lazy_static = "1.4.0"
+
+[target.'cfg(unix)'.dev-dependencies.some_path_dep]
+path = "../../local_dependencies/some_path_dep" # literal string
+features = []
+
+[lib]
+path = "src/lib.rs"
+
+[patch.crates-io]
+some_path_dep = { path = "../../local_dependencies/some_path_dep" }
+
+[replace]
+"some_path_dep:0.1.0" = { path = "../../local_dependencies/some_path_dep" }
+"#;
+        assert_eq!(rewritten, expected);
+    }
+
+    #[test]
+    fn test_vendor_config() {
+        let cargo_vendor_output = r#"
+[source.crates-io]
+replace-with = "vendored-sources"
+
+[source."https://github.com/PyO3/pyo3"]
+git = "https://github.com/PyO3/pyo3"
+rev = "2c7ac2"
+replace-with = "vendored-sources"
+
+[source.vendored-sources]
+directory = "/tmp/.tmpK1pDWx/vendor"
+"#;
+        let config: toml::Value =
+            toml::from_str(&vendor_config(cargo_vendor_output).unwrap()).unwrap();
+        assert_eq!(
+            config["source"]["vendored-sources"]["directory"].as_str(),
+            Some("vendor")
+        );
+        assert_eq!(
+            config["source"]["crates-io"]["replace-with"].as_str(),
+            Some("vendored-sources")
+        );
+        assert!(vendor_config("").is_err());
+    }
+
+    #[test]
+    fn test_sdist_files_include_exclude() {
+        let manifest_path = Path::new("test-crates/hello-world/Cargo.toml");
+        let cargo_toml = CargoToml::from_path(manifest_path).unwrap();
+        let metadata21 =
+            Metadata21::from_cargo_toml(&cargo_toml, manifest_path.parent().unwrap()).unwrap();
+        // The repository root has a src directory too, which must not be matched
+        let include = vec!["src/*.rs".to_string()];
+        let exclude = vec!["check_installed/**".to_string()];
+        let files =
+            sdist_files(&metadata21, manifest_path, Some(&include), Some(&exclude)).unwrap();
+
+        let main_rs = &files.files[Path::new("src/main.rs")];
+        assert_eq!(main_rs.1, "matches sdist-include \"src/*.rs\"");
+        assert!(!files.files.contains_key(Path::new("src/lib.rs")));
+        assert!(files.files.contains_key(Path::new("pyproject.toml")));
+        assert!(files.files.contains_key(Path::new("PKG-INFO")));
+        assert_eq!(
+            files
+                .excluded
+                .get(Path::new("check_installed/check_installed.py")),
+            Some(&"check_installed/**".to_string())
+        );
+        assert!(!files
+            .files
+            .contains_key(Path::new("check_installed/check_installed.py")));
+    }
+
+    #[test]
+    fn test_sdist_files_exclude_path_dependency() {
+        let manifest_path = Path::new("test-crates/lib_with_path_dep/Cargo.toml");
+        let cargo_toml = CargoToml::from_path(manifest_path).unwrap();
+        let metadata21 =
+            Metadata21::from_cargo_toml(&cargo_toml, manifest_path.parent().unwrap()).unwrap();
+        // Matches the path in the source distribution
+        let exclude = vec!["local_dependencies/*/src/lib.rs".to_string()];
+        let files = sdist_files(&metadata21, manifest_path, None, Some(&exclude)).unwrap();
+
+        let excluded = Path::new("local_dependencies/some_path_dep/src/lib.rs");
+        assert_eq!(
+            files.excluded.get(excluded),
+            Some(&"local_dependencies/*/src/lib.rs".to_string())
+        );
+        assert!(!files.files.contains_key(excluded));
+        assert!(files.files.contains_key(Path::new("src/lib.rs")));
+    }
+
+    #[test]
+    fn test_not_ignored_files() {
+        let dir = tempfile::tempdir().unwrap();
+        // The gitignore only applies in a git repository
+        fs::create_dir_all(dir.path().join(".git")).unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::create_dir_all(dir.path().join("generated")).unwrap();
+        fs::write(dir.path().join(".gitignore"), "generated/\n").unwrap();
+        fs::write(dir.path().join("src").join("lib.rs"), "").unwrap();
+        fs::write(dir.path().join("generated").join("lib.rs"), "").unwrap();
+
+        let files = not_ignored_files(dir.path()).unwrap();
+        let expected: HashSet<PathBuf> =
+            vec![PathBuf::from(".gitignore"), Path::new("src").join("lib.rs")]
+                .into_iter()
+                .collect();
+        assert_eq!(files, expected);
+    }
+
+    #[test]
+    fn test_source_distribution_up_to_date() {
+        let manifest_path = Path::new("test-crates/hello-world/Cargo.toml");
+        let cargo_toml = CargoToml::from_path(manifest_path).unwrap();
+        let metadata21 =
+            Metadata21::from_cargo_toml(&cargo_toml, manifest_path.parent().unwrap()).unwrap();
+        let tempdir = tempfile::tempdir().unwrap();
+        let build = || {
+            source_distribution(
+                tempdir.path(),
+                &metadata21,
+                manifest_path,
+                None,
+                None,
+                false,
+            )
+            .unwrap()
+        };
+
+        let sdist = build();
+        // An unchanged source distribution isn't written again
+        fs::write(&sdist, b"reused").unwrap();
+        assert_eq!(build(), sdist);
+        assert_eq!(fs::read(&sdist).unwrap(), b"reused");
+
+        fs::remove_file(&sdist).unwrap();
+        build();
+        assert_ne!(fs::read(&sdist).unwrap(), b"reused");
+    }
+
+    #[test]
+    fn test_sdist_files_pyproject_excluded() {
+        let manifest_path = Path::new("test-crates/hello-world/Cargo.toml");
+        let cargo_toml = CargoToml::from_path(manifest_path).unwrap();
+        let metadata21 =
+            Metadata21::from_cargo_toml(&cargo_toml, manifest_path.parent().unwrap()).unwrap();
+        let exclude = vec!["*.toml".to_string()];
+        assert!(sdist_files(&metadata21, manifest_path, None, Some(&exclude)).is_err());
+    }
+
+    #[test]
+    fn test_find_workspace() {
+        let member = Path::new("test-crates/workspace/crates/hello-member/Cargo.toml");
+        let (_, member_dir) = find_workspace(member).unwrap().unwrap();
+        assert_eq!(member_dir, Path::new("crates").join("hello-member"));
+        assert!(
+            find_workspace(Path::new("test-crates/hello-world/Cargo.toml"))
+                .unwrap()
+                .is_none()
+        );
+    }
+}
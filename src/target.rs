@@ -1,4 +1,5 @@
 use anyhow::{bail, format_err, Result};
+use goblin::elf::header::{EM_386, EM_AARCH64, EM_ARM, EM_X86_64};
 use platform_info::*;
 use serde::{Deserialize, Serialize};
 use std::env;
@@ -31,10 +32,119 @@ pub enum Manylinux {
     Manylinux2014,
     /// Use the manylinux2014 tag but don't check for compliance
     Manylinux2014Unchecked,
+    /// Use the PEP 600 `manylinux_<x>_<y>` tag for glibc x.y and check for compliance
+    Manylinux(u16, u16),
+    /// Use the PEP 600 `manylinux_<x>_<y>` tag for glibc x.y but don't check for compliance
+    ManylinuxUnchecked(u16, u16),
+    /// Use the PEP 656 `musllinux_<x>_<y>` tag for musl x.y and check for compliance
+    Musllinux(u16, u16),
+    /// Use the PEP 656 `musllinux_<x>_<y>` tag for musl x.y but don't check for compliance
+    MusllinuxUnchecked(u16, u16),
+    /// Use the oldest manylinux tag the compiled library complies with
+    Auto,
     /// Use the native linux tag
     Off,
 }
 
+impl Manylinux {
+    /// Returns the glibc version the policy is based on, e.g. `(2, 17)` for manylinux2014, or
+    /// `None` for `auto` and `off`
+    pub fn glibc_version(&self) -> Option<(u16, u16)> {
+        match *self {
+            Manylinux::Manylinux1 | Manylinux::Manylinux1Unchecked => Some((2, 5)),
+            Manylinux::Manylinux2010 | Manylinux::Manylinux2010Unchecked => Some((2, 12)),
+            Manylinux::Manylinux2014 | Manylinux::Manylinux2014Unchecked => Some((2, 17)),
+            Manylinux::Manylinux(major, minor) | Manylinux::ManylinuxUnchecked(major, minor) => {
+                Some((major, minor))
+            }
+            Manylinux::Musllinux(_, _)
+            | Manylinux::MusllinuxUnchecked(_, _)
+            | Manylinux::Auto
+            | Manylinux::Off => None,
+        }
+    }
+
+    /// Returns the musl version of a musllinux policy, e.g. `(1, 1)` for musllinux_1_1
+    pub fn musl_version(&self) -> Option<(u16, u16)> {
+        match *self {
+            Manylinux::Musllinux(major, minor) | Manylinux::MusllinuxUnchecked(major, minor) => {
+                Some((major, minor))
+            }
+            _ => None,
+        }
+    }
+
+    /// Maps a manylinux policy to the equivalent musllinux policy for musl targets, keeping
+    /// whether the library is checked for compliance. The glibc policies all map to the
+    /// oldest musllinux policy, musllinux_1_1.
+    pub fn for_musl(&self) -> Manylinux {
+        match *self {
+            Manylinux::Musllinux(_, _)
+            | Manylinux::MusllinuxUnchecked(_, _)
+            | Manylinux::Auto
+            | Manylinux::Off => self.clone(),
+            _ if self.is_checked() => Manylinux::Musllinux(1, 1),
+            _ => Manylinux::MusllinuxUnchecked(1, 1),
+        }
+    }
+
+    /// Returns the policy for a glibc version, using the legacy variants where there is an alias
+    pub fn from_glibc_version(major: u16, minor: u16, checked: bool) -> Manylinux {
+        match ((major, minor), checked) {
+            ((2, 5), true) => Manylinux::Manylinux1,
+            ((2, 5), false) => Manylinux::Manylinux1Unchecked,
+            ((2, 12), true) => Manylinux::Manylinux2010,
+            ((2, 12), false) => Manylinux::Manylinux2010Unchecked,
+            ((2, 17), true) => Manylinux::Manylinux2014,
+            ((2, 17), false) => Manylinux::Manylinux2014Unchecked,
+            (_, true) => Manylinux::Manylinux(major, minor),
+            (_, false) => Manylinux::ManylinuxUnchecked(major, minor),
+        }
+    }
+
+    /// Whether the compiled library is checked for compliance with this policy
+    pub fn is_checked(&self) -> bool {
+        match *self {
+            Manylinux::Manylinux1
+            | Manylinux::Manylinux2010
+            | Manylinux::Manylinux2014
+            | Manylinux::Manylinux(_, _)
+            | Manylinux::Musllinux(_, _)
+            | Manylinux::Auto => true,
+            Manylinux::Manylinux1Unchecked
+            | Manylinux::Manylinux2010Unchecked
+            | Manylinux::Manylinux2014Unchecked
+            | Manylinux::ManylinuxUnchecked(_, _)
+            | Manylinux::MusllinuxUnchecked(_, _)
+            | Manylinux::Off => false,
+        }
+    }
+
+    /// Returns the names for the platform tag without the architecture, the PEP 600 name
+    /// first and then the legacy alias if there is one, e.g.
+    /// `["manylinux_2_17", "manylinux2014"]`
+    pub fn tag_names(&self) -> Vec<String> {
+        if let Some((major, minor)) = self.musl_version() {
+            return vec![format!("musllinux_{}_{}", major, minor)];
+        }
+        match self.glibc_version() {
+            Some((major, minor)) => {
+                let mut names = vec![format!("manylinux_{}_{}", major, minor)];
+                match (major, minor) {
+                    (2, 5) => names.push("manylinux1".to_string()),
+                    (2, 12) => names.push("manylinux2010".to_string()),
+                    (2, 17) => names.push("manylinux2014".to_string()),
+                    _ => {}
+                }
+                names
+            }
+            // For auto, the actual policy is only known after compiling, so we can only promise
+            // the native linux tag up front
+            None => vec!["linux".to_string()],
+        }
+    }
+}
+
 impl fmt::Display for Manylinux {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -44,6 +154,15 @@ impl fmt::Display for Manylinux {
             Manylinux::Manylinux2010Unchecked => write!(f, "manylinux2010"),
             Manylinux::Manylinux2014 => write!(f, "manylinux2014"),
             Manylinux::Manylinux2014Unchecked => write!(f, "manylinux2014"),
+            Manylinux::Manylinux(major, minor) => write!(f, "manylinux_{}_{}", major, minor),
+            Manylinux::ManylinuxUnchecked(major, minor) => {
+                write!(f, "manylinux_{}_{}", major, minor)
+            }
+            Manylinux::Musllinux(major, minor) => write!(f, "musllinux_{}_{}", major, minor),
+            Manylinux::MusllinuxUnchecked(major, minor) => {
+                write!(f, "musllinux_{}_{}", major, minor)
+            }
+            Manylinux::Auto => write!(f, "linux"),
             Manylinux::Off => write!(f, "linux"),
         }
     }
@@ -53,15 +172,39 @@ impl FromStr for Manylinux {
     type Err = &'static str;
 
     fn from_str(value: &str) -> Result<Self, Self::Err> {
-        match value {
+        let value = value.to_lowercase();
+        match value.as_str() {
             "1" => Ok(Manylinux::Manylinux1),
             "1-unchecked" => Ok(Manylinux::Manylinux1Unchecked),
             "2010" => Ok(Manylinux::Manylinux2010),
             "2010-unchecked" => Ok(Manylinux::Manylinux2010Unchecked),
-            "2014" => Ok(Manylinux::Manylinux2014Unchecked),
+            "2014" => Ok(Manylinux::Manylinux2014),
             "2014-unchecked" => Ok(Manylinux::Manylinux2014Unchecked),
+            "auto" => Ok(Manylinux::Auto),
             "off" => Ok(Manylinux::Off),
-            _ => Err("Invalid value for the manylinux option"),
+            _ => {
+                // PEP 600 tags given by glibc version, e.g. `2_24` or `2_24-unchecked`, and
+                // PEP 656 tags given with their name, e.g. `musllinux_1_1`
+                let err = "Invalid value for the manylinux option";
+                let (version, checked) = if value.ends_with("-unchecked") {
+                    (value.trim_end_matches("-unchecked"), false)
+                } else {
+                    (value.as_str(), true)
+                };
+                let (version, musl) = if version.starts_with("musllinux_") {
+                    (version.trim_start_matches("musllinux_"), true)
+                } else {
+                    (version, false)
+                };
+                let mut parts = version.splitn(2, '_');
+                let major = parts.next().ok_or(err)?.parse().map_err(|_| err)?;
+                let minor = parts.next().ok_or(err)?.parse().map_err(|_| err)?;
+                Ok(match (musl, checked) {
+                    (true, true) => Manylinux::Musllinux(major, minor),
+                    (true, false) => Manylinux::MusllinuxUnchecked(major, minor),
+                    (false, _) => Manylinux::from_glibc_version(major, minor, checked),
+                })
+            }
         }
     }
 }
@@ -86,11 +229,20 @@ impl fmt::Display for Arch {
     }
 }
 
+/// The C library of the target, which decides between manylinux and musllinux on linux
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Environment {
+    Gnu,
+    Musl,
+    Other,
+}
+
 /// The part of the current platform that is relevant when building wheels and is supported
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Target {
     os: OS,
     arch: Arch,
+    env: Environment,
 }
 
 impl Target {
@@ -123,6 +275,14 @@ impl Target {
             unsupported => bail!("The architecture {:?} is not supported", unsupported),
         };
 
+        let env = match platform.target_env {
+            Some(platforms::target::Env::Musl) => Environment::Musl,
+            Some(platforms::target::Env::GNU) => Environment::Gnu,
+            // Many gnu targets don't set an env since they don't need one for disambiguation
+            None if os == OS::Linux => Environment::Gnu,
+            _ => Environment::Other,
+        };
+
         // bail on any unsupported targets
         match (&os, &arch) {
             (OS::FreeBSD, Arch::AARCH64) => bail!("aarch64 is not supported for FreeBSD"),
@@ -141,7 +301,7 @@ impl Target {
             (OS::Windows, Arch::ARM7L) => bail!("arm7l is not supported for Windows"),
             (_, _) => {}
         }
-        Ok(Target { os, arch })
+        Ok(Target { os, arch, env })
     }
 
     /// Returns whether the platform is 64 bit or 32 bit
@@ -159,11 +319,56 @@ impl Target {
         self.os != OS::Windows
     }
 
+    /// Returns the name of the architecture as used in the platform tag, e.g. `x86_64`
+    pub fn get_platform_arch(&self) -> String {
+        self.arch.to_string()
+    }
+
+    /// Returns the manylinux policy that is used unless one is given, which is the oldest
+    /// policy that exists for the architecture: manylinux1 for x86 and manylinux2014 for
+    /// aarch64 and armv7
+    pub fn get_default_manylinux(&self, checked: bool) -> Manylinux {
+        match self.arch {
+            Arch::AARCH64 | Arch::ARM7L => Manylinux::from_glibc_version(2, 17, checked),
+            Arch::X86 | Arch::X86_64 => Manylinux::from_glibc_version(2, 5, checked),
+        }
+    }
+
     /// Returns true if the current platform is linux
     pub fn is_linux(&self) -> bool {
         self.os == OS::Linux
     }
 
+    /// Returns the file name of the dynamic loader that linux elf files of this target link,
+    /// e.g. `ld-linux-x86-64.so.2`
+    pub fn get_dynamic_loader(&self) -> &'static str {
+        match (&self.arch, self.is_musl_libc()) {
+            (Arch::AARCH64, false) => "ld-linux-aarch64.so.1",
+            (Arch::ARM7L, false) => "ld-linux-armhf.so.3",
+            (Arch::X86, false) => "ld-linux.so.2",
+            (Arch::X86_64, false) => "ld-linux-x86-64.so.2",
+            (Arch::AARCH64, true) => "ld-musl-aarch64.so.1",
+            (Arch::ARM7L, true) => "ld-musl-armhf.so.1",
+            (Arch::X86, true) => "ld-musl-i386.so.1",
+            (Arch::X86_64, true) => "ld-musl-x86_64.so.1",
+        }
+    }
+
+    /// Returns the elf machine type (`e_machine` in the elf header) of the architecture
+    pub fn get_elf_machine(&self) -> u16 {
+        match self.arch {
+            Arch::AARCH64 => EM_AARCH64,
+            Arch::ARM7L => EM_ARM,
+            Arch::X86 => EM_386,
+            Arch::X86_64 => EM_X86_64,
+        }
+    }
+
+    /// Returns true if the target links against musl instead of glibc
+    pub fn is_musl_libc(&self) -> bool {
+        self.env == Environment::Musl
+    }
+
     /// Returns true if the current platform is freebsd
     pub fn is_freebsd(&self) -> bool {
         self.os == OS::FreeBSD
@@ -180,6 +385,10 @@ impl Target {
     }
 
     /// Returns the platform part of the tag for the wheel name for cffi wheels
+    ///
+    /// For manylinux policies with a legacy alias, this is a compressed tag set with both names,
+    /// e.g. `manylinux_2_17_x86_64.manylinux2014_x86_64`. For musl targets, the manylinux
+    /// policy is mapped to the equivalent musllinux policy.
     pub fn get_platform_tag(&self, manylinux: &Manylinux) -> String {
         match (&self.os, &self.arch) {
             (OS::FreeBSD, Arch::X86_64) => {
@@ -190,7 +399,19 @@ impl Target {
                 let release = info.release().replace(".", "_").replace("-", "_");
                 format!("freebsd_{}_amd64", release)
             }
-            (OS::Linux, _) => format!("{}_{}", manylinux, self.arch),
+            (OS::Linux, _) if self.is_musl_libc() => manylinux
+                .for_musl()
+                .tag_names()
+                .iter()
+                .map(|name| format!("{}_{}", name, self.arch))
+                .collect::<Vec<_>>()
+                .join("."),
+            (OS::Linux, _) => manylinux
+                .tag_names()
+                .iter()
+                .map(|name| format!("{}_{}", name, self.arch))
+                .collect::<Vec<_>>()
+                .join("."),
             (OS::Macos, Arch::X86_64) => "macosx_10_7_x86_64".to_string(),
             (OS::Windows, Arch::X86) => "win32".to_string(),
             (OS::Windows, Arch::X86_64) => "win_amd64".to_string(),
@@ -203,8 +424,19 @@ impl Target {
         vec![format!("py3-none-{}", self.get_platform_tag(&manylinux))]
     }
 
-    /// Returns the platform for the tag in the shared libaries file name
-    pub fn get_shared_platform_tag(&self) -> &'static str {
+    /// Returns the platform for the tag in the shared libaries file name.
+    ///
+    /// CPython only distinguishes musl in the extension suffix since 3.13, older versions use
+    /// the glibc platform on musl too (e.g. `x86_64-linux-gnu`)
+    pub fn get_shared_platform_tag(&self, python_version: (usize, usize)) -> &'static str {
+        if self.is_musl_libc() && python_version >= (3, 13) {
+            return match self.arch {
+                Arch::AARCH64 => "aarch64-linux-musl",
+                Arch::ARM7L => "arm-linux-musleabihf",
+                Arch::X86 => "i386-linux-musl",
+                Arch::X86_64 => "x86_64-linux-musl",
+            };
+        }
         match (&self.os, &self.arch) {
             (OS::FreeBSD, _) => "", // according imp.get_suffixes(), there are no such
             (OS::Linux, Arch::AARCH64) => "aarch64-linux-gnu", // aka armv8-linux-gnueabihf
@@ -271,3 +503,86 @@ impl Target {
         (tag, tags)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_manylinux_from_str() {
+        assert_eq!(Manylinux::from_str("2014"), Ok(Manylinux::Manylinux2014));
+        assert_eq!(Manylinux::from_str("2_12"), Ok(Manylinux::Manylinux2010));
+        assert_eq!(Manylinux::from_str("2_24"), Ok(Manylinux::Manylinux(2, 24)));
+        assert_eq!(
+            Manylinux::from_str("2_24-unchecked"),
+            Ok(Manylinux::ManylinuxUnchecked(2, 24))
+        );
+        assert_eq!(
+            Manylinux::from_str("musllinux_1_1"),
+            Ok(Manylinux::Musllinux(1, 1))
+        );
+        assert_eq!(
+            Manylinux::from_str("musllinux_1_2-unchecked"),
+            Ok(Manylinux::MusllinuxUnchecked(1, 2))
+        );
+        assert!(Manylinux::from_str("2_x").is_err());
+        assert!(Manylinux::from_str("2024").is_err());
+    }
+
+    #[test]
+    fn test_manylinux_tag_names() {
+        assert_eq!(
+            Manylinux::Manylinux2014Unchecked.tag_names(),
+            vec!["manylinux_2_17", "manylinux2014"]
+        );
+        assert_eq!(
+            Manylinux::Manylinux(2, 24).tag_names(),
+            vec!["manylinux_2_24"]
+        );
+        assert_eq!(Manylinux::Off.tag_names(), vec!["linux"]);
+        assert_eq!(
+            Manylinux::MusllinuxUnchecked(1, 2).tag_names(),
+            vec!["musllinux_1_2"]
+        );
+    }
+
+    #[test]
+    fn test_dynamic_loader() {
+        for (triple, loader) in &[
+            ("aarch64-unknown-linux-gnu", "ld-linux-aarch64.so.1"),
+            ("armv7-unknown-linux-gnueabihf", "ld-linux-armhf.so.3"),
+            ("i686-unknown-linux-gnu", "ld-linux.so.2"),
+        ] {
+            let target = Target::from_target_triple(Some(triple.to_string())).unwrap();
+            assert_eq!(&target.get_dynamic_loader(), loader);
+        }
+    }
+
+    #[test]
+    fn test_musl_target() {
+        let target =
+            Target::from_target_triple(Some("x86_64-unknown-linux-musl".to_string())).unwrap();
+        assert!(target.is_musl_libc());
+        assert_eq!(
+            target.get_platform_tag(&Manylinux::Manylinux2014),
+            "musllinux_1_1_x86_64"
+        );
+        assert_eq!(
+            target.get_platform_tag(&Manylinux::Musllinux(1, 2)),
+            "musllinux_1_2_x86_64"
+        );
+        assert_eq!(target.get_platform_tag(&Manylinux::Off), "linux_x86_64");
+        assert_eq!(target.get_shared_platform_tag((3, 8)), "x86_64-linux-gnu");
+        assert_eq!(target.get_shared_platform_tag((3, 13)), "x86_64-linux-musl");
+        assert_eq!(target.get_dynamic_loader(), "ld-musl-x86_64.so.1");
+
+        let target =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        assert!(!target.is_musl_libc());
+        assert_eq!(target.get_dynamic_loader(), "ld-linux-x86-64.so.2");
+        assert_eq!(
+            target.get_platform_tag(&Manylinux::Manylinux2014),
+            "manylinux_2_17_x86_64.manylinux2014_x86_64"
+        );
+    }
+}
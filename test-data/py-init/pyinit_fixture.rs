@@ -0,0 +1,31 @@
+//! The source of the pyinit_fixture.dll and pyinit_fixture.dylib fixtures, which are minimal
+//! libraries exporting `PyInit_fixture` for testing `warn_missing_py_init` on linux. They were
+//! built without the standard library for the windows and macOS targets with nightly rustc and
+//! linked with the rust-lld that ships with rustup:
+//!
+//! ```shell
+//! rustc +nightly --crate-type lib --emit obj -C panic=abort -C opt-level=z \
+//!     --target x86_64-pc-windows-msvc pyinit_fixture.rs -o pyinit_fixture.obj
+//! rust-lld -flavor link /dll /noentry /nodefaultlib /export:PyInit_fixture \
+//!     /out:pyinit_fixture.dll pyinit_fixture.obj
+//! rustc +nightly --crate-type lib --emit obj -C panic=abort -C opt-level=z \
+//!     --target x86_64-apple-darwin pyinit_fixture.rs -o pyinit_fixture.o
+//! rust-lld -flavor darwin -dylib -arch x86_64 -platform_version macos 10.12.0 10.12.0 \
+//!     -install_name pyinit_fixture.dylib -o pyinit_fixture.dylib pyinit_fixture.o
+//! ```
+
+#![feature(no_core, lang_items)]
+#![allow(internal_features)]
+#![no_core]
+
+#[lang = "pointee_sized"]
+pub trait PointeeSized {}
+
+#[lang = "meta_sized"]
+pub trait MetaSized: PointeeSized {}
+
+#[lang = "sized"]
+pub trait Sized: MetaSized {}
+
+#[no_mangle]
+pub extern "C" fn PyInit_fixture() {}
@@ -1,8 +1,10 @@
+use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::str;
 
 use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
 use structopt::StructOpt;
 
 use maturin::{BuildOptions, Target};
@@ -43,6 +45,38 @@ fn test_integration_hello_world() {
     handle_result(test_integration("test-crates/hello-world", None));
 }
 
+#[test]
+fn test_integration_sdist_lib_with_path_dep() {
+    handle_result(test_integration_sdist(
+        "test-crates/lib_with_path_dep",
+        "lib_with_path_dep-0.1.0",
+    ));
+}
+
+#[test]
+fn test_integration_sdist_vendored() {
+    handle_result(test_integration_sdist_vendor(
+        "test-crates/lib_with_path_dep",
+        "lib_with_path_dep-0.1.0",
+    ));
+}
+
+#[test]
+fn test_integration_sdist_vendored_wheel() {
+    handle_result(test_integration_sdist_vendor_wheel(
+        "test-crates/lib_with_path_dep",
+        "lib_with_path_dep-0.1.0",
+    ));
+}
+
+#[test]
+fn test_integration_sdist_hello_world() {
+    handle_result(test_integration_sdist(
+        "test-crates/hello-world",
+        "hello_world-0.1.0",
+    ));
+}
+
 /// For each installed python version, this builds a wheel, creates a virtualenv if it
 /// doesn't exist, installs the package and runs check_installed.py
 fn test_integration(package: impl AsRef<Path>, bindings: Option<String>) -> Result<()> {
@@ -58,6 +92,9 @@ fn test_integration(package: impl AsRef<Path>, bindings: Option<String>) -> Resu
         "--manifest-path",
         &package_string,
         "--cargo-extra-args='--quiet'",
+        // The host's glibc is usually too recent for manylinux1 and we only check that the
+        // wheels work here. Compliance is checked by test-dockerfile.sh
+        "--manylinux=off",
     ];
 
     if let Some(ref bindings) = bindings {
@@ -138,6 +175,153 @@ fn test_integration(package: impl AsRef<Path>, bindings: Option<String>) -> Resu
     Ok(())
 }
 
+/// Builds a source distribution, checks that all files are in the `{name}-{version}` directory,
+/// unpacks it and builds a wheel from the unpacked sources
+fn test_integration_sdist(package: impl AsRef<Path>, sdist_dir: &str) -> Result<()> {
+    maybe_mock_cargo();
+
+    let tempdir = tempfile::tempdir()?;
+    let out = tempdir.path().join("dist").display().to_string();
+    let manifest_path = package.as_ref().join("Cargo.toml").display().to_string();
+
+    // The first argument is ignored by clap
+    let cli = vec!["build", "--manifest-path", &manifest_path, "--out", &out];
+    let sdist = BuildOptions::from_iter_safe(cli)?
+        .into_build_context(false, false)?
+        .build_source_distribution(false)?
+        .context("Expected a source distribution to be built")?
+        .0;
+
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(&sdist)?));
+    for entry in archive.entries()? {
+        let path = entry?.path()?.to_path_buf();
+        if !path.starts_with(sdist_dir) {
+            bail!("{} is not in {} in the sdist", path.display(), sdist_dir);
+        }
+    }
+
+    let unpacked = tempdir.path().join("unpacked");
+    tar::Archive::new(GzDecoder::new(File::open(&sdist)?)).unpack(&unpacked)?;
+
+    let manifest_path = unpacked
+        .join(sdist_dir)
+        .join("Cargo.toml")
+        .display()
+        .to_string();
+    let cli = vec![
+        "build",
+        "--manifest-path",
+        &manifest_path,
+        "--out",
+        &out,
+        "--cargo-extra-args='--quiet'",
+        "--manylinux=off",
+    ];
+    let wheels = BuildOptions::from_iter_safe(cli)?
+        .into_build_context(false, cfg!(feature = "faster-tests"))?
+        .build_wheels()?;
+    if wheels.is_empty() {
+        bail!("No wheel was built from the source distribution");
+    }
+    for (filename, _, _) in wheels {
+        if !filename.is_file() {
+            bail!("{} wasn't built", filename.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds a source distribution with vendored dependencies, unpacks it and checks that cargo
+/// can build it offline
+fn test_integration_sdist_vendor(package: impl AsRef<Path>, sdist_dir: &str) -> Result<()> {
+    maybe_mock_cargo();
+
+    let tempdir = tempfile::tempdir()?;
+    let out = tempdir.path().join("dist").display().to_string();
+    let manifest_path = package.as_ref().join("Cargo.toml").display().to_string();
+
+    // The first argument is ignored by clap
+    let cli = vec!["build", "--manifest-path", &manifest_path, "--out", &out];
+    let sdist = BuildOptions::from_iter_safe(cli)?
+        .into_build_context(false, false)?
+        .build_source_distribution(true)?
+        .context("Expected a source distribution to be built")?
+        .0;
+
+    let unpacked = tempdir.path().join("unpacked");
+    tar::Archive::new(GzDecoder::new(File::open(&sdist)?)).unpack(&unpacked)?;
+    let sdist_root = unpacked.join(sdist_dir);
+    if !maturin::is_vendored(&sdist_root) {
+        bail!("The source distribution doesn't contain the vendored dependencies");
+    }
+
+    // Cargo reads the .cargo/config.toml from the working directory, like when pip builds the
+    // unpacked source distribution
+    let output = Command::new("cargo")
+        .args(&["build", "--offline", "--locked", "--quiet"])
+        .env("CARGO_TARGET_DIR", tempdir.path().join("target"))
+        .current_dir(&sdist_root)
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "Failed to build the vendored source distribution: {}\n--- Stdout:\n{}\n--- Stderr:\n{}",
+            output.status,
+            str::from_utf8(&output.stdout)?,
+            str::from_utf8(&output.stderr)?,
+        );
+    }
+
+    Ok(())
+}
+
+/// Builds a source distribution with vendored dependencies, unpacks it and builds a wheel from it
+/// the way pip does, with an empty cargo home so that nothing can be fetched
+fn test_integration_sdist_vendor_wheel(package: impl AsRef<Path>, sdist_dir: &str) -> Result<()> {
+    let tempdir = tempfile::tempdir()?;
+    let out = tempdir.path().join("dist").display().to_string();
+    let manifest_path = package.as_ref().join("Cargo.toml").display().to_string();
+
+    // The first argument is ignored by clap
+    let cli = vec!["build", "--manifest-path", &manifest_path, "--out", &out];
+    let sdist = BuildOptions::from_iter_safe(cli)?
+        .into_build_context(false, false)?
+        .build_source_distribution(true)?
+        .context("Expected a source distribution to be built")?
+        .0;
+
+    let unpacked = tempdir.path().join("unpacked");
+    tar::Archive::new(GzDecoder::new(File::open(&sdist)?)).unpack(&unpacked)?;
+    let sdist_root = unpacked.join(sdist_dir);
+
+    let wheel_dir = tempdir.path().join("wheels");
+    let output = Command::new(env!("CARGO_BIN_EXE_maturin"))
+        .args(&["pep517", "build-wheel", "--manylinux=off", "--out"])
+        .arg(&wheel_dir)
+        .env("CARGO_HOME", tempdir.path().join("cargo-home"))
+        .env("CARGO_TARGET_DIR", tempdir.path().join("target"))
+        .current_dir(&sdist_root)
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "Failed to build a wheel from the vendored source distribution: {}\n--- Stdout:\n{}\n--- Stderr:\n{}",
+            output.status,
+            str::from_utf8(&output.stdout)?,
+            str::from_utf8(&output.stderr)?,
+        );
+    }
+
+    let filename = str::from_utf8(&output.stdout)?
+        .lines()
+        .last()
+        .context("maturin didn't print the filename of the wheel")?;
+    if !wheel_dir.join(filename).is_file() {
+        bail!("{} wasn't built", filename);
+    }
+
+    Ok(())
+}
+
 /// Creates conda environments
 #[cfg(target_os = "windows")]
 fn create_conda_env(name: &str, major: usize, minor: usize) {
@@ -181,6 +365,9 @@ fn test_integration_conda(package: impl AsRef<Path>, bindings: Option<String>) -
         "--manifest-path",
         &package_string,
         "--cargo-extra-args='--quiet'",
+        // The host's glibc is usually too recent for manylinux1 and we only check that the
+        // wheels work here. Compliance is checked by test-dockerfile.sh
+        "--manylinux=off",
     ];
 
     if let Some(ref bindings) = bindings {